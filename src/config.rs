@@ -0,0 +1,231 @@
+//! Text forms of the line settings, for command line tools and config files
+
+use std::{fmt, str::FromStr};
+
+use crate::{DataBits, Error, FlowControl, Parity, PortConfig, StopBits};
+
+fn invalid(setting: &str, s: &str) -> Error {
+    Error::InvalidArgument(format!("invalid {setting}: {s:?}"))
+}
+
+impl fmt::Display for DataBits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DataBits::Five => "5",
+            DataBits::Six => "6",
+            DataBits::Seven => "7",
+            DataBits::Eight => "8",
+            DataBits::Unknown => "unknown",
+        })
+    }
+}
+
+/// Parses `5` to `8`
+impl FromStr for DataBits {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "5" => Ok(DataBits::Five),
+            "6" => Ok(DataBits::Six),
+            "7" => Ok(DataBits::Seven),
+            "8" => Ok(DataBits::Eight),
+            _ => Err(invalid("data bits", s)),
+        }
+    }
+}
+
+impl fmt::Display for Parity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Parity::None => "none",
+            Parity::Odd => "odd",
+            Parity::Even => "even",
+            Parity::Mark => "mark",
+            Parity::Space => "space",
+            Parity::Unknown => "unknown",
+        })
+    }
+}
+
+/// Parses the names `Display` gives, or their initial as in `8N1`, ignoring
+/// case
+impl FromStr for Parity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" | "n" => Ok(Parity::None),
+            "odd" | "o" => Ok(Parity::Odd),
+            "even" | "e" => Ok(Parity::Even),
+            "mark" | "m" => Ok(Parity::Mark),
+            "space" | "s" => Ok(Parity::Space),
+            _ => Err(invalid("parity", s)),
+        }
+    }
+}
+
+impl fmt::Display for StopBits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StopBits::One => "1",
+            StopBits::OnePointFive => "1.5",
+            StopBits::Two => "2",
+            StopBits::Unknown => "unknown",
+        })
+    }
+}
+
+/// Parses `1`, `1.5` or `2`
+impl FromStr for StopBits {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "1" => Ok(StopBits::One),
+            "1.5" => Ok(StopBits::OnePointFive),
+            "2" => Ok(StopBits::Two),
+            _ => Err(invalid("stop bits", s)),
+        }
+    }
+}
+
+impl fmt::Display for FlowControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FlowControl::None => "none",
+            FlowControl::Software => "software",
+            FlowControl::Hardware => "hardware",
+            FlowControl::Unknown => "unknown",
+        })
+    }
+}
+
+/// Parses the names `Display` gives, or `xonxoff` and `rtscts`, ignoring case
+impl FromStr for FlowControl {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(FlowControl::None),
+            "software" | "xonxoff" => Ok(FlowControl::Software),
+            "hardware" | "rtscts" => Ok(FlowControl::Hardware),
+            _ => Err(invalid("flow control", s)),
+        }
+    }
+}
+
+/// Parses the baud rate and framing as `115200:8N1` or `115200:8:N:1`
+///
+/// Flow control cannot be given this way and is `FlowControl::None`.
+impl FromStr for PortConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let (baud_rate, data_bits, parity, stop_bits) = match parts[..] {
+            [baud_rate, data_bits, parity, stop_bits] => (baud_rate, data_bits, parity, stop_bits),
+            // The data bits and parity are a character each
+            [baud_rate, framing] if framing.is_ascii() && framing.len() > 2 => {
+                (baud_rate, &framing[..1], &framing[1..2], &framing[2..])
+            }
+            _ => return Err(invalid("port config", s)),
+        };
+
+        Ok(PortConfig {
+            baud_rate: baud_rate
+                .trim()
+                .parse()
+                .map_err(|_| invalid("baud rate", baud_rate))?,
+            data_bits: data_bits.parse()?,
+            flow_control: FlowControl::None,
+            parity: parity.parse()?,
+            stop_bits: stop_bits.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DataBits, FlowControl, Parity, PortConfig, StopBits};
+
+    #[test]
+    fn test_round_trip() {
+        for data_bits in [
+            DataBits::Five,
+            DataBits::Six,
+            DataBits::Seven,
+            DataBits::Eight,
+        ] {
+            assert_eq!(
+                data_bits.to_string().parse::<DataBits>().unwrap(),
+                data_bits
+            );
+        }
+        for parity in [
+            Parity::None,
+            Parity::Odd,
+            Parity::Even,
+            Parity::Mark,
+            Parity::Space,
+        ] {
+            assert_eq!(parity.to_string().parse::<Parity>().unwrap(), parity);
+        }
+        for stop_bits in [StopBits::One, StopBits::OnePointFive, StopBits::Two] {
+            assert_eq!(
+                stop_bits.to_string().parse::<StopBits>().unwrap(),
+                stop_bits
+            );
+        }
+        for flow_control in [
+            FlowControl::None,
+            FlowControl::Software,
+            FlowControl::Hardware,
+        ] {
+            let parsed = flow_control.to_string().parse::<FlowControl>().unwrap();
+            assert_eq!(parsed, flow_control);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("E".parse::<Parity>().unwrap(), Parity::Even);
+        assert_eq!(
+            "RtsCts".parse::<FlowControl>().unwrap(),
+            FlowControl::Hardware
+        );
+        assert!("unknown".parse::<Parity>().is_err());
+        assert!("9".parse::<DataBits>().is_err());
+        assert!("".parse::<StopBits>().is_err());
+    }
+
+    #[test]
+    fn test_parse_port_config() {
+        let expected = PortConfig {
+            baud_rate: 115200,
+            data_bits: DataBits::Eight,
+            flow_control: FlowControl::None,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        };
+        assert_eq!("115200:8N1".parse::<PortConfig>().unwrap(), expected);
+        assert_eq!("115200:8:n:1".parse::<PortConfig>().unwrap(), expected);
+
+        let config = "9600:7E1.5".parse::<PortConfig>().unwrap();
+        assert_eq!(config.data_bits, DataBits::Seven);
+        assert_eq!(config.parity, Parity::Even);
+        assert_eq!(config.stop_bits, StopBits::OnePointFive);
+
+        for invalid in [
+            "",
+            "8N1",
+            "115200",
+            "fast:8N1",
+            "115200:8N",
+            "115200:8X1",
+            "9600:ä1",
+        ] {
+            assert!(invalid.parse::<PortConfig>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::{future::Future, time::Duration};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+
+use crate::{Clear, Error, Result, SerialPort};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// An integer type [`SerialPortExt`] reads and writes, such as `u16` or `i32`
+///
+/// Implemented for the signed and unsigned integers of 8 to 128 bits.
+pub trait Integer: sealed::Sealed + Copy + Send {
+    #[doc(hidden)]
+    type Bytes: AsRef<[u8]> + AsMut<[u8]> + Default + Send;
+    #[doc(hidden)]
+    fn from_le(bytes: Self::Bytes) -> Self;
+    #[doc(hidden)]
+    fn from_be(bytes: Self::Bytes) -> Self;
+    #[doc(hidden)]
+    fn to_le(self) -> Self::Bytes;
+    #[doc(hidden)]
+    fn to_be(self) -> Self::Bytes;
+}
+
+macro_rules! impl_integer {
+    ($($int:ty),*) => {$(
+        impl sealed::Sealed for $int {}
+
+        impl Integer for $int {
+            type Bytes = [u8; size_of::<$int>()];
+
+            fn from_le(bytes: Self::Bytes) -> Self {
+                <$int>::from_le_bytes(bytes)
+            }
+
+            fn from_be(bytes: Self::Bytes) -> Self {
+                <$int>::from_be_bytes(bytes)
+            }
+
+            fn to_le(self) -> Self::Bytes {
+                self.to_le_bytes()
+            }
+
+            fn to_be(self) -> Self::Bytes {
+                self.to_be_bytes()
+            }
+        }
+    )*};
+}
+
+impl_integer!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// Helpers for framed protocols: multi-byte integers in a fixed byte order,
+/// and frames delimited by silence
+///
+/// ```no_run
+/// # async fn run() -> serialport::Result<()> {
+/// use std::time::Duration;
+///
+/// use serialport::SerialPortExt;
+///
+/// let mut port = serialport::new("/dev/ttyUSB0", 115200).open()?;
+/// port.write_be(0x0102u16).await?;
+/// let len: u32 = port.read_le(Duration::from_millis(100)).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Reads gather the bytes of the integer over as many reads as it takes and
+/// fail with [`Error::TimedOut`] if they do not all arrive within the
+/// timeout; the bytes that did arrive are lost then. Writes are queued like
+/// any other write, so flush to wait for them.
+pub trait SerialPortExt: AsyncRead + AsyncWrite + Unpin + Send {
+    /// Reads a little-endian integer
+    fn read_le<T: Integer>(&mut self, timeout: Duration) -> impl Future<Output = Result<T>> + Send {
+        async move { Ok(T::from_le(read_bytes::<Self, T>(self, timeout).await?)) }
+    }
+
+    /// Reads a big-endian integer
+    fn read_be<T: Integer>(&mut self, timeout: Duration) -> impl Future<Output = Result<T>> + Send {
+        async move { Ok(T::from_be(read_bytes::<Self, T>(self, timeout).await?)) }
+    }
+
+    /// Writes a little-endian integer
+    fn write_le<T: Integer>(&mut self, value: T) -> impl Future<Output = Result<()>> + Send {
+        async move { Ok(self.write_all(value.to_le().as_ref()).await?) }
+    }
+
+    /// Writes a big-endian integer
+    fn write_be<T: Integer>(&mut self, value: T) -> impl Future<Output = Result<()>> + Send {
+        async move { Ok(self.write_all(value.to_be().as_ref()).await?) }
+    }
+
+    /// Reads a frame delimited by silence, as Modbus RTU frames are: the
+    /// bytes received until the line stays quiet for `idle_gap`
+    ///
+    /// Waits up to `overall_timeout` for the first byte, failing with
+    /// [`Error::TimedOut`] if none arrives. The frame also ends once it holds
+    /// `max_len` bytes, or when `overall_timeout` runs out mid-frame, in
+    /// which case it is returned as far as it got.
+    ///
+    /// The gap is timed from when a read hands bytes over, not from when they
+    /// crossed the line, so it is a lower bound. Timers resolve about a
+    /// millisecond, so gaps much shorter than that, e.g. 3.5 character times
+    /// at high baud rates, cannot be told apart from bytes arriving back to
+    /// back; USB adapters that deliver in bursts every few milliseconds can
+    /// also open gaps inside a frame. On Windows, a port opened with
+    /// `read_coalesce` only hands bytes over once the line was quiet for the
+    /// interval timeout (`ReadIntervalTimeout`), which then adds to
+    /// `idle_gap`; without it, reads return as soon as bytes arrive.
+    fn read_until_idle(
+        &mut self,
+        idle_gap: Duration,
+        max_len: usize,
+        overall_timeout: Duration,
+    ) -> impl Future<Output = Result<Vec<u8>>> + Send {
+        async move {
+            let deadline = Instant::now() + overall_timeout;
+            let mut frame = vec![0; max_len];
+            let mut len = 0;
+
+            while len < max_len {
+                // The first byte may take until the deadline, the others
+                // only as long as the gap
+                let until = match len {
+                    0 => deadline,
+                    _ => (Instant::now() + idle_gap).min(deadline),
+                };
+                match tokio::time::timeout_at(until, self.read(&mut frame[len..])).await {
+                    Ok(Ok(0)) if len == 0 => return Err(Error::Disconnected(None)),
+                    Ok(Ok(0)) => break,
+                    Ok(Ok(n)) => len += n,
+                    Ok(Err(e)) => return Err(e.into()),
+                    Err(_) if len == 0 => return Err(Error::TimedOut),
+                    Err(_) => break,
+                }
+            }
+
+            frame.truncate(len);
+            Ok(frame)
+        }
+    }
+
+    /// Measures the round trip of `probe` through an echoing peer, e.g. a
+    /// loopback plug or a device that sends back what it receives
+    ///
+    /// The input buffer is cleared first so stale bytes are not taken for the
+    /// echo, then `probe` is written and read back. The time from starting the
+    /// write to receiving the last byte of the echo is returned; it includes
+    /// the time the probe takes on the line and whatever the path adds, such
+    /// as the latency timer of an FTDI adapter. Fails with
+    /// [`Error::TimedOut`] if the echo is not back within `timeout`, and with
+    /// an `InvalidData` I/O error if it differs from `probe`.
+    fn ping(
+        &mut self,
+        probe: &[u8],
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Duration>> + Send
+    where
+        Self: SerialPort,
+    {
+        async move {
+            if probe.is_empty() {
+                return Err(Error::InvalidArgument("empty probe".to_owned()));
+            }
+            self.clear(Clear::Input)?;
+
+            let start = Instant::now();
+            let mut echo = vec![0; probe.len()];
+            let round_trip = async {
+                self.write_all(probe).await?;
+                self.flush().await?;
+                self.read_exact(&mut echo).await
+            };
+            tokio::time::timeout_at(start + timeout, round_trip)
+                .await
+                .map_err(|_| Error::TimedOut)??;
+            let elapsed = start.elapsed();
+
+            if echo != probe {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "echo differs from the probe",
+                )
+                .into());
+            }
+            Ok(elapsed)
+        }
+    }
+}
+
+impl<P: AsyncRead + AsyncWrite + Unpin + Send + ?Sized> SerialPortExt for P {}
+
+async fn read_bytes<P, T>(port: &mut P, timeout: Duration) -> Result<T::Bytes>
+where
+    P: AsyncRead + Unpin + ?Sized,
+    T: Integer,
+{
+    let mut bytes = T::Bytes::default();
+    tokio::time::timeout(timeout, port.read_exact(bytes.as_mut()))
+        .await
+        .map_err(|_| Error::TimedOut)??;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::SerialPortExt;
+    use crate::Error;
+
+    #[tokio::test]
+    async fn test_byte_order() {
+        let (mut a, mut b) = tokio::io::duplex(64);
+        let timeout = Duration::from_secs(1);
+
+        a.write_le(0x0102u16).await.unwrap();
+        a.write_be(0x0102u16).await.unwrap();
+        a.write_le(-2i32).await.unwrap();
+        let mut buf = [0; 8];
+        b.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [0x02, 0x01, 0x01, 0x02, 0xfe, 0xff, 0xff, 0xff]);
+
+        b.write_all(&[0x02, 0x01, 0x01, 0x02]).await.unwrap();
+        assert_eq!(a.read_le::<u16>(timeout).await.unwrap(), 0x0102);
+        assert_eq!(a.read_be::<u16>(timeout).await.unwrap(), 0x0102);
+    }
+
+    #[tokio::test]
+    async fn test_read_until_idle() {
+        let (mut a, mut b) = tokio::io::duplex(64);
+        let gap = Duration::from_millis(50);
+        let timeout = Duration::from_secs(1);
+
+        tokio::spawn(async move {
+            b.write_all(b"fir").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            b.write_all(b"st").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            b.write_all(b"second frame").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        });
+
+        // A pause shorter than the gap stays within the frame
+        assert_eq!(a.read_until_idle(gap, 64, timeout).await.unwrap(), b"first");
+        // Full before the line goes quiet
+        assert_eq!(a.read_until_idle(gap, 6, timeout).await.unwrap(), b"second");
+        assert_eq!(
+            a.read_until_idle(gap, 64, timeout).await.unwrap(),
+            b" frame"
+        );
+
+        let silent = a.read_until_idle(gap, 64, Duration::from_millis(10)).await;
+        assert!(matches!(silent, Err(Error::TimedOut)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_ping() {
+        let (mut master, mut slave) = crate::pty_pair().unwrap();
+        let timeout = Duration::from_secs(1);
+
+        // Stale input is not taken for the echo
+        master.write_all(b"stale").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let echo = tokio::spawn(async move {
+            let mut buf = [0; 4];
+            master.read_exact(&mut buf).await.unwrap();
+            master.write_all(&buf).await.unwrap();
+            master
+        });
+        assert!(slave.ping(b"ping", timeout).await.unwrap() < timeout);
+
+        // Nothing echoes any more
+        let _master = echo.await.unwrap();
+        let silent = slave.ping(b"ping", Duration::from_millis(20)).await;
+        assert!(matches!(silent, Err(Error::TimedOut)));
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        let (mut a, mut b) = tokio::io::duplex(64);
+
+        // Only half the integer arrives
+        b.write_all(&[0x01, 0x02]).await.unwrap();
+        let err = a.read_le::<u32>(Duration::from_millis(10)).await;
+        assert!(matches!(err, Err(Error::TimedOut)));
+    }
+}
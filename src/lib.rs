@@ -1,15 +1,48 @@
-use std::{io, os::windows::io::AsRawHandle};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd as AsRawIo;
+#[cfg(windows)]
+use std::os::windows::io::AsRawHandle as AsRawIo;
+use std::{
+    io,
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::Duration,
+};
 
 #[cfg(unix)]
 mod posix;
 #[cfg(unix)]
-pub use posix::TTYPort;
+use posix::TTYPort as NativePort;
+#[cfg(unix)]
+pub use posix::{BreakDuration, TTYPort};
+
+mod config;
+
+mod ext;
+pub use ext::{Integer, SerialPortExt};
+
+#[cfg(feature = "test-util")]
+mod mock;
+#[cfg(feature = "test-util")]
+pub use mock::{MockHandle, MockPort};
+
+mod reconnect;
+pub use reconnect::ReconnectingPort;
+
+mod shared;
+pub use shared::SharedPort;
 
 #[cfg(windows)]
 mod windows;
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+#[cfg(windows)]
+use windows::COMPort as NativePort;
 #[cfg(windows)]
-pub use windows::COMPort;
+pub use windows::{
+    COMPort, Capabilities, CommEventMask, CommTimeouts, ModemStatus, PortProperties,
+    ProviderSubType, PurgeFlags,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -19,10 +52,51 @@ pub enum Error {
     InvalidArgument(String),
     #[error("Not implemented for target platform")]
     NotImplemented,
+    /// Another process holds the port, along with the OS error saying so
+    #[error("Port is busy")]
+    Busy(#[source] Option<io::Error>),
+    #[error("Operation timed out")]
+    TimedOut,
+    /// The device is gone, e.g. a USB adapter was unplugged, along with the
+    /// OS error that gave it away, if any
+    #[error("Device disconnected")]
+    Disconnected(#[source] Option<io::Error>),
+    /// Timed out after transferring only this many bytes
+    #[error("Operation timed out after transferring {0} bytes")]
+    Incomplete(usize),
+    /// Received data was lost because a buffer overflowed, see
+    /// [`OverrunPolicy::Error`]
+    #[error("Received data was lost to an overrun")]
+    Overrun,
+    /// The operation was abandoned because its cancellation signal fired
+    #[error("Operation cancelled")]
+    Cancelled,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Returns the OS error code behind this error, if any
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Error::Io(err) | Error::Busy(Some(err)) | Error::Disconnected(Some(err)) => {
+                err.raw_os_error()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Io(err) => err,
+            Error::Disconnected(_) => io::Error::new(io::ErrorKind::BrokenPipe, err),
+            err => io::Error::other(err),
+        }
+    }
+}
+
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -31,6 +105,8 @@ pub enum DataBits {
     Six,
     Seven,
     Eight,
+    /// A size none of the others describes, which some drivers report; on
+    /// Windows `COMPort::raw_data_bits` tells the size itself
     Unknown,
 }
 
@@ -40,6 +116,10 @@ pub enum Parity {
     None,
     Odd,
     Even,
+    /// The parity bit is always 1
+    Mark,
+    /// The parity bit is always 0
+    Space,
     Unknown,
 }
 
@@ -61,12 +141,142 @@ pub enum FlowControl {
     Unknown,
 }
 
+/// Which flow control mechanisms a port has enabled, each on its own, see
+/// [`SerialPort::flow_control_state`]
+///
+/// A mechanism counts as enabled if it governs either direction.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlowControlState {
+    /// Output follows CTS, or RTS signals whether input can be taken
+    pub rts_cts: bool,
+    /// Output follows DSR, or DTR signals whether input can be taken; never
+    /// read on Unix
+    pub dsr_dtr: bool,
+    /// Output stops on XOFF, or XOFF is sent once input backs up
+    pub xon_xoff: bool,
+}
+
+impl FlowControlState {
+    /// The single mode that describes the state, or [`FlowControl::Unknown`]
+    /// if hardware and software flow control are both on
+    pub fn mode(&self) -> FlowControl {
+        match (self.rts_cts || self.dsr_dtr, self.xon_xoff) {
+            (false, false) => FlowControl::None,
+            (true, false) => FlowControl::Hardware,
+            (false, true) => FlowControl::Software,
+            (true, true) => FlowControl::Unknown,
+        }
+    }
+}
+
+impl From<FlowControl> for FlowControlState {
+    fn from(flow_control: FlowControl) -> FlowControlState {
+        FlowControlState {
+            rts_cts: flow_control == FlowControl::Hardware,
+            dsr_dtr: false,
+            xon_xoff: flow_control == FlowControl::Software,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Clear {
+    /// Discard received bytes and abort a read in flight
     Input,
+    /// Discard bytes not yet sent and abort a write in flight
     Output,
+    /// Both [`Clear::Input`] and [`Clear::Output`]
     All,
+    /// Discard received bytes, leaving a read in flight to wait for new ones
+    InputSoft,
+    /// Discard bytes not yet sent, leaving a write in flight to complete
+    ///
+    /// The driver may still send the part of the write it has not handed to
+    /// the UART yet.
+    OutputSoft,
+}
+
+/// Which other openers may share the port while it is held open.
+///
+/// Most serial drivers do not actually support concurrent openers and will
+/// refuse a second open regardless of the share mode, so anything other than
+/// [`ShareMode::Exclusive`] is best-effort. On Unix, any other mode lets every
+/// other opener in.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShareMode {
+    #[default]
+    Exclusive,
+    /// Other openers may read, e.g. a sniffer next to the owning process.
+    Read,
+    /// Other openers may write.
+    Write,
+    /// Other openers may read and write.
+    ReadWrite,
+}
+
+/// Which directions a port is opened for, see [`SerialPortBuilder::access`]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Access {
+    #[default]
+    ReadWrite,
+    /// Writes fail, e.g. for a sniffer that must not disturb the line
+    ReadOnly,
+    /// Reads fail
+    WriteOnly,
+}
+
+impl Access {
+    /// Fails a read on a port opened write-only, before the OS would
+    pub(crate) fn check_read(self) -> io::Result<()> {
+        match self {
+            Access::WriteOnly => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "port is opened write-only",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails a write on a port opened read-only, before the OS would
+    pub(crate) fn check_write(self) -> io::Result<()> {
+        match self {
+            Access::ReadOnly => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "port is opened read-only",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What a read does once received data was lost to an overrun
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OverrunPolicy {
+    /// Carry on with the data that did arrive
+    #[default]
+    Ignore,
+    /// Fail the read with [`Error::Overrun`]; the next read carries on
+    Error,
+    /// Discard everything received so far and carry on with what arrives
+    /// next, so a read never returns data with a gap in it
+    Clear,
+}
+
+/// The level RTS is driven to while transmitting, see
+/// [`SerialPortBuilder::rs485_manual`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RtsLevel {
+    /// RTS is raised to transmit and lowered to receive, as most transceivers
+    /// with the driver enable on RTS expect
+    High,
+    /// RTS is lowered to transmit and raised to receive
+    Low,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -77,9 +287,37 @@ pub struct SerialPortBuilder {
     flow_control: FlowControl,
     parity: Parity,
     stop_bits: StopBits,
+    share_mode: ShareMode,
+    access: Access,
+    open_timeout: Option<Duration>,
+    verify_config: bool,
+    check_max_baud_rate: bool,
+    purge_on_open: bool,
+    ignore_carrier: bool,
+    flush_on_drop: bool,
+    preserve_settings: bool,
+    queue_sizes: Option<(u32, u32)>,
+    flow_limits: Option<(u16, u16)>,
+    overrun_policy: OverrunPolicy,
+    recover_rx_overflow: bool,
+    read_coalesce: Option<Duration>,
+    error_replacement: Option<u8>,
+    rs485_manual: Option<RtsLevel>,
 }
 
 impl SerialPortBuilder {
+    /// A builder for `path` with all the line settings of `config`, e.g. one
+    /// loaded from a configuration file
+    ///
+    /// The other options start out as with [`new`].
+    pub fn with_config(path: &str, config: PortConfig) -> SerialPortBuilder {
+        new(path, config.baud_rate)
+            .data_bits(config.data_bits)
+            .flow_control(config.flow_control)
+            .parity(config.parity)
+            .stop_bits(config.stop_bits)
+    }
+
     #[must_use]
     pub fn path(mut self, path: &str) -> Self {
         path.clone_into(&mut self.path);
@@ -116,20 +354,368 @@ impl SerialPortBuilder {
         self
     }
 
+    #[must_use]
+    pub fn share_mode(mut self, share_mode: ShareMode) -> Self {
+        self.share_mode = share_mode;
+        self
+    }
+
+    /// Opens the port for reading, writing or both, [`Access::ReadWrite`] by
+    /// default
+    ///
+    /// Asking for one direction only lets a port be opened with permission
+    /// for just that one. I/O in the other direction then fails with
+    /// [`io::ErrorKind::PermissionDenied`] without reaching the driver; the
+    /// line settings and control lines can still be changed.
+    #[must_use]
+    pub fn access(mut self, access: Access) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Gives up opening the port with [`Error::TimedOut`] after `timeout`
+    ///
+    /// Bluetooth virtual ports connect their channel while being opened, which
+    /// can take several seconds or hang for good if the device is gone.
+    #[must_use]
+    pub fn open_timeout(mut self, timeout: Duration) -> Self {
+        self.open_timeout = Some(timeout);
+        self
+    }
+
+    /// Reads the settings back after applying them, failing with
+    /// [`Error::InvalidArgument`] if the driver silently ignored any of them
+    ///
+    /// This applies to opening the port as well as to the setters afterwards.
+    #[must_use]
+    pub fn verify_config(mut self, verify: bool) -> Self {
+        self.verify_config = verify;
+        self
+    }
+
+    /// Checks baud rates against the maximum the driver reports, failing with
+    /// [`Error::InvalidArgument`] rather than leaving the driver to reject
+    /// them with a less telling error
+    ///
+    /// This applies to opening the port as well as to the setters afterwards.
+    /// Drivers without a fixed maximum accept any rate, see
+    /// `COMPort::max_baud_rate`. This has no effect on Unix.
+    #[must_use]
+    pub fn check_max_baud_rate(mut self, check: bool) -> Self {
+        self.check_max_baud_rate = check;
+        self
+    }
+
+    /// Discards whatever the driver still buffers from before the port was
+    /// opened, so the first read does not return stale bytes of an earlier
+    /// session
+    ///
+    /// Both directions are cleared once the settings are applied. Off by
+    /// default.
+    #[must_use]
+    pub fn purge_on_open(mut self, purge: bool) -> Self {
+        self.purge_on_open = purge;
+        self
+    }
+
+    /// Whether the port ignores the carrier detect (DCD) line, the default,
+    /// or follows it as a modem connection
+    ///
+    /// This sets or clears `CLOCAL`. Opening never waits for the carrier
+    /// either way, as the port is opened non-blocking, see `TTYPort::open`;
+    /// `CLOCAL` only takes effect once the settings are applied. Following
+    /// the carrier, the device hangs up once it drops, so reads and writes
+    /// fail with [`Error::Disconnected`] from then on and the port has to be
+    /// reopened; some drivers also hold writes back until the carrier is
+    /// present. This has no effect on Windows, where carrier detect never
+    /// gates I/O.
+    #[must_use]
+    pub fn ignore_carrier(mut self, ignore: bool) -> Self {
+        self.ignore_carrier = ignore;
+        self
+    }
+
+    /// Has dropping the port wait for the data written so far to be sent
+    /// rather than abort the write still in flight, so a program that exits
+    /// right after writing does not lose the tail of its output
+    ///
+    /// Dropping then blocks until the driver has sent everything, which
+    /// takes as long as the data needs on the line, or for good if flow
+    /// control holds output off; errors are ignored. Flushing and draining
+    /// explicitly before dropping is preferred, as that can be awaited and
+    /// given a timeout, see `COMPort::drain_timeout`. Off by default. This
+    /// has no effect on Unix, where closing the device already waits for
+    /// queued output to be sent.
+    #[must_use]
+    pub fn flush_on_drop(mut self, flush: bool) -> Self {
+        self.flush_on_drop = flush;
+        self
+    }
+
+    /// Opens the port without touching its line settings, for monitoring a
+    /// port another process has configured without disrupting its session
+    ///
+    /// The baud rate, data bits, parity, stop bits, flow control, flow
+    /// limits and error replacement of the builder are then ignored, as is
+    /// [`SerialPortBuilder::ignore_carrier`]; the port runs with whatever
+    /// settings it had. On Unix the device is not put into raw mode either,
+    /// so a terminal left in canonical mode still processes its input.
+    /// Combined with [`Access::ReadOnly`] the port only ever listens. Off by
+    /// default.
+    #[must_use]
+    pub fn preserve_settings(mut self, preserve: bool) -> Self {
+        self.preserve_settings = preserve;
+        self
+    }
+
+    /// Asks the driver for input and output queues of the given sizes, in
+    /// bytes, via `SetupComm`
+    ///
+    /// Drivers are free to round the sizes or ignore them, see
+    /// `COMPort::queue_capacity` for what was actually allocated. This has no
+    /// effect on Unix.
+    #[must_use]
+    pub fn queue_sizes(mut self, input: u32, output: u32) -> Self {
+        self.queue_sizes = Some((input, output));
+        self
+    }
+
+    /// Sets the flow control watermarks of the driver's input queue
+    ///
+    /// Flow control stops the sender once fewer than `xoff_lim` bytes of the
+    /// queue are free, and lets it resume once no more than `xon_lim` bytes
+    /// are queued. The driver keeps the values whatever the [`FlowControl`]
+    /// mode, so they can be tuned ahead of enabling it. By default the
+    /// driver's own are kept, see `COMPort::flow_limits`. This has no effect
+    /// on Unix.
+    #[must_use]
+    pub fn flow_limits(mut self, xon_lim: u16, xoff_lim: u16) -> Self {
+        self.flow_limits = Some((xon_lim, xoff_lim));
+        self
+    }
+
+    /// Sets what a read does once received data was lost to an overrun,
+    /// [`OverrunPolicy::Ignore`] by default
+    ///
+    /// Only overruns the driver reports are noticed, see
+    /// `COMPort::overrun_count`. This has no effect on Unix.
+    #[must_use]
+    pub fn overrun_policy(mut self, policy: OverrunPolicy) -> Self {
+        self.overrun_policy = policy;
+        self
+    }
+
+    /// Has a read that the driver aborts as its input buffer overflowed
+    /// (`CE_RXOVER`) clear the error and carry on, rather than fail
+    ///
+    /// Drivers set to abort I/O on line errors (`fAbortOnError`, e.g. kept
+    /// with [`SerialPortBuilder::preserve_settings`]) end reads that way,
+    /// which keeps a fast link alive through a short burst. Each recovery
+    /// means received data was lost; the overflow is handled by the
+    /// [`SerialPortBuilder::overrun_policy`] like any other, and
+    /// `COMPort::recovered_overflows` tells how often it happened. Off by
+    /// default. This has no effect on Unix.
+    #[must_use]
+    pub fn recover_rx_overflow(mut self, recover: bool) -> Self {
+        self.recover_rx_overflow = recover;
+        self
+    }
+
+    /// Makes reads gather bytes until the line has been quiet for `gap`,
+    /// rather than return whatever arrived so far
+    ///
+    /// At high baud rates this takes far fewer reads and wakeups for the same
+    /// data, at the cost of delivering each burst `gap` late. A read still
+    /// waits for the first byte however long it takes, and completes early
+    /// once its buffer is full. The gap is rounded up to whole milliseconds.
+    /// This has no effect on Unix.
+    #[must_use]
+    pub fn read_coalesce(mut self, gap: Duration) -> Self {
+        self.read_coalesce = Some(gap);
+        self
+    }
+
+    /// Has the driver receive `replacement` in place of each byte that fails
+    /// the parity check, so parsers see where data was corrupted
+    ///
+    /// Bytes are only checked with a [`Parity`] other than `None`. Null bytes
+    /// are never discarded by the driver, so `Some(0)` marks errors with a
+    /// zero byte. `None`, the default, passes corrupted bytes on as received.
+    /// This has no effect on Unix.
+    #[must_use]
+    pub fn error_replacement(mut self, replacement: Option<u8>) -> Self {
+        self.error_replacement = replacement;
+        self
+    }
+
+    /// Drives RTS as the direction line of an RS-485 transceiver without
+    /// automatic direction control, at `level` while transmitting
+    ///
+    /// The line is switched to transmit by the first write and back to
+    /// receive by the next flush, once the driver signals that the last byte
+    /// has left (`EV_TXEMPTY`). Flush after each message, or the line stays
+    /// at the transmit level and the bus cannot answer. How early the line
+    /// drops thus depends on how accurately the driver signals `EV_TXEMPTY`:
+    /// drivers that signal it once the last byte is handed to the UART, not
+    /// once it is shifted out, cut off the end of that byte, and drivers
+    /// that signal it late keep the bus from being turned around in time.
+    /// Check the timing with a scope before relying on it.
+    ///
+    /// The events the port signals are selected for all handles to it at
+    /// once, so this cannot be combined with `COMPort::monitor_modem`; RTS
+    /// must not be set by hand either. This has no effect on Unix, where the
+    /// kernel's RS-485 mode does the same in the driver.
+    #[must_use]
+    pub fn rs485_manual(mut self, level: RtsLevel) -> Self {
+        self.rs485_manual = Some(level);
+        self
+    }
+
     #[cfg(windows)]
     pub fn open(self) -> Result<COMPort> {
         return windows::COMPort::open(&self);
     }
+
+    #[cfg(unix)]
+    pub fn open(self) -> Result<TTYPort> {
+        posix::TTYPort::open(&self)
+    }
+
+    /// Opens the port behind a trait object, so code that runs on several
+    /// platforms can hold the port as one type
+    pub fn open_boxed(self) -> Result<Box<dyn SerialPort>> {
+        Ok(Box::new(self.open()?))
+    }
+
+    /// Opens the port on the blocking thread pool, as opening can take a while
+    pub async fn open_async(self) -> Result<NativePort> {
+        tokio::task::spawn_blocking(move || self.open())
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Opens the port on the blocking thread pool like [`Self::open_async`],
+    /// failing with [`Error::Cancelled`] as soon as `cancel` completes
+    ///
+    /// `cancel` can be any future, e.g. `token.cancelled()` of a
+    /// `tokio_util` `CancellationToken`. An open the OS has already started
+    /// cannot be stopped; if it still succeeds, the port is closed again.
+    pub async fn open_async_with_cancel(
+        self,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<NativePort> {
+        tokio::select! {
+            biased;
+            _ = cancel => Err(Error::Cancelled),
+            result = self.open_async() => result,
+        }
+    }
+
+    /// Opens the port, retrying up to `attempts` times in total while it is busy
+    ///
+    /// USB-serial devices often report busy for a moment right after they are
+    /// plugged in. Only [`Error::Busy`] is retried, with `backoff` between
+    /// attempts; any other error, such as a port that does not exist, is
+    /// returned immediately. If every attempt fails, the last error is returned.
+    pub async fn open_with_retry(self, attempts: u32, backoff: Duration) -> Result<NativePort> {
+        let mut attempt = 1;
+        loop {
+            match self.clone().open_async().await {
+                Err(Error::Busy(_)) if attempt < attempts => {
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                }
+                result => return result,
+            }
+        }
+    }
 }
 
-pub trait SerialPort: Send + AsyncRead + AsyncWrite + AsRawHandle {
+/// A snapshot of the line settings of a port
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortConfig {
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub flow_control: FlowControl,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl PortConfig {
+    /// How long one character takes on the line: the start bit, the data
+    /// bits, the parity bit if any and the stop bits
+    ///
+    /// For example, the 3.5 character times of silence that end a Modbus RTU
+    /// frame are `config.char_time()? * 7 / 2`.
+    pub fn char_time(&self) -> Result<Duration> {
+        let unknown = |setting: &str| Err(Error::InvalidArgument(format!("{setting} unknown")));
+
+        let data = match self.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+            DataBits::Unknown => return unknown("data bits"),
+        };
+        let parity = match self.parity {
+            Parity::None => 0,
+            Parity::Odd | Parity::Even | Parity::Mark | Parity::Space => 1,
+            Parity::Unknown => return unknown("parity"),
+        };
+        let stop_halves = match self.stop_bits {
+            StopBits::One => 2,
+            StopBits::OnePointFive => 3,
+            StopBits::Two => 4,
+            StopBits::Unknown => return unknown("stop bits"),
+        };
+        if self.baud_rate == 0 {
+            return Err(Error::InvalidArgument(
+                "character time needs a baud rate".to_owned(),
+            ));
+        }
+
+        // Counted in half bits for 1.5 stop bits
+        let halves: u64 = 2 * (1 + data + parity) + stop_halves;
+        let nanos = (halves * 1_000_000_000).div_ceil(2 * u64::from(self.baud_rate));
+        Ok(Duration::from_nanos(nanos))
+    }
+}
+
+/// A serial port, as returned by [`SerialPortBuilder::open`]
+///
+/// The trait is dyn-compatible, see [`SerialPortBuilder::open_boxed`].
+pub trait SerialPort: Send + Unpin + AsyncRead + AsyncWrite + AsRawIo {
     fn name(&self) -> String;
     fn baudrate(&self) -> Result<u32>;
     fn data_bits(&self) -> Result<DataBits>;
     fn flow_control(&self) -> Result<FlowControl>;
+
+    /// Returns which flow control mechanisms are enabled, each on its own
+    ///
+    /// Unlike [`SerialPort::flow_control`], this tells mixed settings apart,
+    /// e.g. ones another program left behind, which a single mode cannot
+    /// express. The default implementation derives it from `flow_control`.
+    fn flow_control_state(&self) -> Result<FlowControlState> {
+        Ok(FlowControlState::from(self.flow_control()?))
+    }
+
     fn parity(&self) -> Result<Parity>;
     fn stop_bits(&self) -> Result<StopBits>;
     fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()>;
+
+    /// Sets the baud rate and returns the one the driver settled on
+    ///
+    /// At high or non-standard rates drivers often pick the closest rate the
+    /// hardware can generate instead; protocols with tight timing budgets
+    /// can then work with the actual rate. The rate is read back after
+    /// setting it, so a driver that reports the requested rate while running
+    /// at another cannot be caught. With
+    /// [`SerialPortBuilder::verify_config`], a coerced rate fails instead.
+    fn set_baud_rate_effective(&mut self, baud_rate: u32) -> Result<u32> {
+        self.set_baud_rate(baud_rate)?;
+        self.baudrate()
+    }
     fn set_data_bits(&mut self, data_bits: DataBits) -> Result<()>;
     fn set_flow_control(&mut self, flow_control: FlowControl) -> Result<()>;
     fn set_parity(&mut self, parity: Parity) -> Result<()>;
@@ -137,6 +723,47 @@ pub trait SerialPort: Send + AsyncRead + AsyncWrite + AsRawHandle {
     fn set_rts(&mut self, level: bool) -> Result<()>;
     fn set_dtr(&mut self, level: bool) -> Result<()>;
     fn set_break(&mut self, level: bool) -> Result<()>;
+
+    /// Returns whether a break is asserted on the line, as set with
+    /// [`SerialPort::set_break`] through this port or one of its clones
+    ///
+    /// No platform offers a query for the break a port transmits, so this is
+    /// the state last set through this handle rather than one read back from
+    /// the driver: a break set through another handle to the device is not
+    /// seen, and neither are timed breaks such as `TTYPort::send_break`. On
+    /// Windows, which does not track breaks, this fails with
+    /// [`Error::NotImplemented`].
+    fn break_state(&self) -> Result<bool>;
+
+    /// Holds the TX line at mark, its idle state, or releases it again
+    ///
+    /// The opposite of [`SerialPort::set_break`]: holding mark clears a
+    /// break and suspends output, so the line stays idle and writes wait;
+    /// releasing resumes output. Output is suspended the way a received
+    /// XOFF does it, so with software flow control, releasing also lifts an
+    /// XOFF the peer sent, and the peer's XON lifts the hold. Supported on
+    /// Windows and Unix; the default implementation fails with
+    /// [`Error::NotImplemented`].
+    fn set_mark(&mut self, level: bool) -> Result<()> {
+        let _ = level;
+        Err(Error::NotImplemented)
+    }
+
+    /// Sets RTS and DTR together, leaving a line that is `None` as it is
+    ///
+    /// Meant for reset sequences that need both lines to change at once. On
+    /// Unix both change in a single call; on Windows they are set back to
+    /// back, RTS first, which still leaves a short window between them.
+    fn set_control_lines(&mut self, rts: Option<bool>, dtr: Option<bool>) -> Result<()> {
+        if let Some(rts) = rts {
+            self.set_rts(rts)?;
+        }
+        if let Some(dtr) = dtr {
+            self.set_dtr(dtr)?;
+        }
+        Ok(())
+    }
+
     fn cts(&mut self) -> Result<bool>;
     fn dsr(&mut self) -> Result<bool>;
     fn ri(&mut self) -> Result<bool>;
@@ -144,6 +771,162 @@ pub trait SerialPort: Send + AsyncRead + AsyncWrite + AsRawHandle {
     fn bytes_to_read(&self) -> Result<u32>;
     fn bytes_to_write(&self) -> Result<u32>;
     fn clear(&self, buffer_to_clear: Clear) -> Result<()>;
+
+    /// Returns the line settings the driver actually applied
+    ///
+    /// These are read back from the port rather than remembered from the
+    /// builder, so they reflect any coercion by the driver, e.g. a baud rate
+    /// rounded to the nearest one the hardware can generate.
+    fn effective_config(&self) -> Result<PortConfig> {
+        Ok(PortConfig {
+            baud_rate: self.baudrate()?,
+            data_bits: self.data_bits()?,
+            flow_control: self.flow_control()?,
+            parity: self.parity()?,
+            stop_bits: self.stop_bits()?,
+        })
+    }
+
+    /// A builder for another port configured like this one, e.g. to reopen
+    /// it or to open a second port alongside
+    ///
+    /// The path and the line settings are read back from the port as with
+    /// [`SerialPort::effective_config`]; the native ports also carry over
+    /// their access mode and, on Windows, the flow limits and error
+    /// replacement. Options that only take effect while opening, such as
+    /// [`SerialPortBuilder::purge_on_open`], start out as with [`new`].
+    fn to_builder(&self) -> Result<SerialPortBuilder> {
+        Ok(SerialPortBuilder::with_config(
+            &self.name(),
+            self.effective_config()?,
+        ))
+    }
+
+    /// How long one character takes on the line at the current settings, see
+    /// [`PortConfig::char_time`]
+    fn char_time(&self) -> Result<Duration> {
+        self.effective_config()?.char_time()
+    }
+
+    /// Applies all of `config` at once, leaving the settings as they were if
+    /// any part of it is rejected
+    ///
+    /// Calling the individual setters in turn can leave the port half
+    /// configured when a later one fails. The native ports write the settings
+    /// to the driver in one go and write the previous ones back if the driver
+    /// rejects them; the default implementation calls the setters and rolls
+    /// back through them.
+    fn set_config_atomic(&mut self, config: &PortConfig) -> Result<()> {
+        let saved = self.effective_config()?;
+        apply_config(self, config).inspect_err(|_| {
+            let _ = apply_config(self, &saved);
+        })
+    }
+
+    /// Holds a break for `bit_times` bits at the current baud rate, e.g. 13
+    /// for a LIN break
+    ///
+    /// The length is rounded up to whole milliseconds, with at least one, as
+    /// the timer cannot resolve less; asserting and clearing the break are
+    /// separate calls, so scheduling can stretch it by roughly another
+    /// millisecond. If the future is dropped early, the break stays asserted.
+    fn send_break_bits(&mut self, bit_times: u32) -> impl Future<Output = Result<()>> + Send
+    where
+        Self: Sized,
+    {
+        async move {
+            let duration = break_duration(self.baudrate()?, bit_times)?;
+            self.set_break(true)?;
+            tokio::time::sleep(duration).await;
+            self.set_break(false)
+        }
+    }
+
+    /// Sends a LIN frame header: a break of 13 bit times, the `0x55` sync
+    /// byte and the protected identifier `pid`, see [`lin_protected_id`]
+    ///
+    /// The break is timed by the OS, so it is best-effort: it lasts at least
+    /// 13 bit times but may run a few milliseconds longer, which LIN allows.
+    /// Scheduling also leaves at least the one bit of break delimiter before
+    /// the sync byte.
+    fn send_lin_header(&mut self, pid: u8) -> impl Future<Output = Result<()>> + Send
+    where
+        Self: Sized,
+    {
+        async move {
+            self.send_break_bits(13).await?;
+            self.write_all(&[0x55, pid]).await?;
+            self.flush().await?;
+            Ok(())
+        }
+    }
+
+    /// Sends `request` and reads a reply of `read_len` bytes, for
+    /// request/response protocols
+    ///
+    /// Input left over from earlier exchanges is discarded first, so it cannot
+    /// be mistaken for the reply, and the request is fully written before the
+    /// reply is read. The reply is gathered over as many reads as it takes.
+    ///
+    /// `timeout` covers the whole exchange. If the request cannot be written
+    /// in time, what is left of it is cleared from the output and this fails
+    /// with [`Error::TimedOut`]; if the reply does not all arrive in time, it
+    /// fails with [`Error::Incomplete`] and the partial reply is discarded.
+    fn transact(
+        &mut self,
+        request: &[u8],
+        read_len: usize,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Vec<u8>>> + Send
+    where
+        Self: Sized,
+    {
+        async move {
+            let deadline = Instant::now() + timeout;
+            self.clear(Clear::Input)?;
+
+            let write = async {
+                self.write_all(request).await?;
+                self.flush().await
+            };
+            if let Ok(result) = tokio::time::timeout_at(deadline, write).await {
+                result?;
+            } else {
+                self.clear(Clear::Output)?;
+                return Err(Error::TimedOut);
+            }
+
+            let mut reply = vec![0; read_len];
+            let mut filled = 0;
+            while filled < read_len {
+                match tokio::time::timeout_at(deadline, self.read(&mut reply[filled..])).await {
+                    Ok(n) => filled += n?,
+                    Err(_) => return Err(Error::Incomplete(filled)),
+                }
+            }
+            Ok(reply)
+        }
+    }
+}
+
+fn apply_config<P: SerialPort + ?Sized>(port: &mut P, config: &PortConfig) -> Result<()> {
+    port.set_baud_rate(config.baud_rate)?;
+    port.set_data_bits(config.data_bits)?;
+    port.set_flow_control(config.flow_control)?;
+    port.set_parity(config.parity)?;
+    port.set_stop_bits(config.stop_bits)
+}
+
+/// Whether a port is backed by real hardware, determined on a best-effort basis
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PortType {
+    /// A UART on the mainboard or an expansion card, or a USB-serial adapter
+    Physical,
+    /// A port emulated in software, e.g. by com0com or a Bluetooth stack
+    Virtual,
+    #[default]
+    Unknown,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
@@ -153,6 +936,11 @@ pub struct PortInfo {
     pub path: String,
     // friendly name
     pub name: String,
+    // physical or virtual device
+    pub port_type: PortType,
+    // whether the port could be opened when probed, `None` unless listed by
+    // `available_ports_openable`
+    pub available: Option<bool>,
 }
 
 pub fn new<'a>(path: &str, baudrate: u32) -> SerialPortBuilder {
@@ -163,9 +951,53 @@ pub fn new<'a>(path: &str, baudrate: u32) -> SerialPortBuilder {
         flow_control: FlowControl::None,
         parity: Parity::None,
         stop_bits: StopBits::One,
+        share_mode: ShareMode::Exclusive,
+        access: Access::ReadWrite,
+        open_timeout: None,
+        verify_config: false,
+        check_max_baud_rate: false,
+        purge_on_open: false,
+        ignore_carrier: true,
+        flush_on_drop: false,
+        preserve_settings: false,
+        queue_sizes: None,
+        flow_limits: None,
+        overrun_policy: OverrunPolicy::Ignore,
+        recover_rx_overflow: false,
+        read_coalesce: None,
+        error_replacement: None,
+        rs485_manual: None,
     }
 }
 
+/// How long `bit_times` bits last at `baud_rate`, rounded up to the whole
+/// milliseconds the timer can resolve
+pub(crate) fn break_duration(baud_rate: u32, bit_times: u32) -> Result<Duration> {
+    if baud_rate == 0 {
+        return Err(Error::InvalidArgument(
+            "break length in bits needs a baud rate".to_owned(),
+        ));
+    }
+
+    let micros = (u64::from(bit_times) * 1_000_000).div_ceil(u64::from(baud_rate));
+    Ok(Duration::from_millis(micros.div_ceil(1000).max(1)))
+}
+
+/// The protected identifier of LIN frame `frame_id` (0 to 63): the ID with
+/// its two parity bits on top, as sent in a frame header
+pub fn lin_protected_id(frame_id: u8) -> Result<u8> {
+    if frame_id > 0x3F {
+        return Err(Error::InvalidArgument(format!(
+            "LIN frame ID {frame_id} exceeds 6 bits"
+        )));
+    }
+
+    let bit = |n: u8| (frame_id >> n) & 1;
+    let p0 = bit(0) ^ bit(1) ^ bit(2) ^ bit(4);
+    let p1 = !(bit(1) ^ bit(3) ^ bit(4) ^ bit(5)) & 1;
+    Ok(frame_id | (p0 << 6) | (p1 << 7))
+}
+
 pub fn available_ports() -> Result<Vec<PortInfo>> {
     #[cfg(unix)]
     return crate::posix::available_ports();
@@ -177,9 +1009,286 @@ pub fn available_ports() -> Result<Vec<PortInfo>> {
     Err(Error::NotImplemented)
 }
 
+/// Like [`available_ports`], but gives up on devices that stall the
+/// enumeration for longer than `timeout`
+///
+/// Enumeration runs on a thread of its own, which is left to finish in the
+/// background on timeout. On Windows, the ports read before the deadline are
+/// returned then; the result is only [`Error::TimedOut`] if there are none.
+/// Elsewhere the ports only become available all at once, so a timeout is
+/// always [`Error::TimedOut`].
+pub fn available_ports_timeout(timeout: Duration) -> Result<Vec<PortInfo>> {
+    let found = Arc::new(Mutex::new(Vec::new()));
+    let (tx, rx) = mpsc::channel();
+
+    let sink = Arc::clone(&found);
+    thread::spawn(move || {
+        let push = |info| {
+            if let Ok(mut found) = sink.lock() {
+                found.push(info);
+            }
+        };
+
+        #[cfg(windows)]
+        let result = crate::windows::for_each_port(push);
+        #[cfg(not(windows))]
+        let result = available_ports().map(|infos| infos.into_iter().for_each(push));
+
+        let _ = tx.send(result);
+    });
+
+    let finished = rx.recv_timeout(timeout);
+    let found = match found.lock() {
+        Ok(mut found) => std::mem::take(&mut *found),
+        Err(_) => Vec::new(),
+    };
+    match finished {
+        Ok(result) => result.map(|()| found),
+        Err(_) if found.is_empty() => Err(Error::TimedOut),
+        Err(_) => Ok(found),
+    }
+}
+
+/// Like [`available_ports`], but also tries to open each port to tell which
+/// are held by another process, e.g. to grey those out in a port picker
+///
+/// Each port is opened and closed again at once; a port in use is only
+/// probed, never disturbed. Opening a free port can toggle its modem control
+/// lines, though, which resets some boards such as Arduinos. Another process
+/// may take or release a port right after it was probed, so
+/// [`PortInfo::available`] is only advisory.
+pub fn available_ports_openable() -> Result<Vec<PortInfo>> {
+    let mut ports = available_ports()?;
+    for port in &mut ports {
+        #[cfg(unix)]
+        let available = crate::posix::is_openable(&port.path);
+        #[cfg(windows)]
+        let available = crate::windows::is_openable(&port.path);
+        port.available = Some(available);
+    }
+    Ok(ports)
+}
+
+/// Lists the ports `pred` accepts, e.g. to match on several fields of
+/// [`PortInfo`] at once
+///
+/// ```no_run
+/// use serialport::PortType;
+///
+/// let usb = serialport::available_ports_filtered(|port| {
+///     port.port_type == PortType::Physical && port.name.contains("USB")
+/// })?;
+/// # Ok::<(), serialport::Error>(())
+/// ```
+pub fn available_ports_filtered<F: Fn(&PortInfo) -> bool>(pred: F) -> Result<Vec<PortInfo>> {
+    Ok(filter_ports(available_ports()?, pred))
+}
+
+fn filter_ports<F: Fn(&PortInfo) -> bool>(mut ports: Vec<PortInfo>, pred: F) -> Vec<PortInfo> {
+    ports.retain(pred);
+    ports
+}
+
+/// Lists the ports whose friendly name contains `substr`, ignoring case,
+/// e.g. `"CP210x"` or `"Arduino"`
+///
+/// Ports without a friendly name never match, not even an empty `substr`.
+pub fn find_ports_by_name(substr: &str) -> Result<Vec<PortInfo>> {
+    available_ports_filtered(|port| name_matches(&port.name, substr))
+}
+
+fn name_matches(name: &str, substr: &str) -> bool {
+    !name.is_empty() && name.to_lowercase().contains(&substr.to_lowercase())
+}
+
+/// Opens a connected pair of pseudo terminals, `(master, slave)`, to exercise
+/// serial port code without hardware
+///
+/// Whatever is written to one end can be read from the other. The slave is in
+/// raw mode, so bytes pass through unchanged. See [`TTYPort::pair`].
+#[cfg(unix)]
+pub fn pty_pair() -> Result<(TTYPort, TTYPort)> {
+    TTYPort::pair()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{available_ports, new};
+    use std::{io, time::Duration};
+
+    #[cfg(unix)]
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::{
+        DataBits, Error, FlowControl, FlowControlState, Parity, PortConfig, PortInfo, PortType,
+        SerialPort, SerialPortBuilder, StopBits, available_ports, break_duration, filter_ports,
+        name_matches, new,
+    };
+
+    #[test]
+    fn test_error_source() {
+        use std::error::Error as _;
+
+        let err = Error::Disconnected(Some(io::Error::from_raw_os_error(5)));
+        assert_eq!(err.raw_os_error(), Some(5));
+        let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.raw_os_error(), Some(5));
+
+        // The OS error stays reachable through an `io::Error` as well
+        let io_err = io::Error::from(err);
+        assert_eq!(io_err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(io_err.get_ref().unwrap().source().is_some());
+
+        assert!(Error::Busy(None).source().is_none());
+        assert_eq!(Error::TimedOut.raw_os_error(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_error_from_nix() {
+        let err = Error::from(nix::Error::EIO);
+        assert!(matches!(err, Error::Disconnected(Some(_))));
+        assert_eq!(err.raw_os_error(), Some(nix::libc::EIO));
+    }
+
+    #[test]
+    fn test_name_matches() {
+        let name = "Silicon Labs CP210x USB to UART Bridge (COM3)";
+        assert!(name_matches(name, "CP210x"));
+        assert!(name_matches(name, "cp210X"));
+        assert!(name_matches(name, ""));
+        assert!(!name_matches(name, "Arduino"));
+        assert!(!name_matches("", ""));
+        assert!(!name_matches("", "CP210x"));
+    }
+
+    #[test]
+    #[ignore = "manual"]
+    fn test_find_ports_by_name() {
+        println!("{:#?}", crate::find_ports_by_name("USB"))
+    }
+
+    #[test]
+    fn test_flow_control_state_mode() {
+        let state = |rts_cts, dsr_dtr, xon_xoff| FlowControlState {
+            rts_cts,
+            dsr_dtr,
+            xon_xoff,
+        };
+        assert_eq!(state(false, false, false).mode(), FlowControl::None);
+        assert_eq!(state(true, false, false).mode(), FlowControl::Hardware);
+        assert_eq!(state(false, true, false).mode(), FlowControl::Hardware);
+        assert_eq!(state(false, false, true).mode(), FlowControl::Software);
+        assert_eq!(state(true, false, true).mode(), FlowControl::Unknown);
+
+        for mode in [
+            FlowControl::None,
+            FlowControl::Software,
+            FlowControl::Hardware,
+        ] {
+            assert_eq!(FlowControlState::from(mode).mode(), mode);
+        }
+    }
+
+    #[test]
+    fn test_break_duration() {
+        // A LIN break at 19200 baud is well below the timer resolution
+        assert_eq!(break_duration(19200, 13).unwrap(), Duration::from_millis(1));
+        assert_eq!(break_duration(1200, 13).unwrap(), Duration::from_millis(11));
+        assert_eq!(break_duration(9600, 0).unwrap(), Duration::from_millis(1));
+        assert!(break_duration(0, 13).is_err());
+    }
+
+    #[test]
+    fn test_char_time() {
+        let mut config = PortConfig {
+            baud_rate: 9600,
+            data_bits: DataBits::Eight,
+            flow_control: FlowControl::None,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        };
+        // 10 bits
+        assert_eq!(config.char_time().unwrap(), Duration::from_nanos(1_041_667));
+
+        config.parity = Parity::Even;
+        config.stop_bits = StopBits::Two;
+        assert_eq!(config.char_time().unwrap(), Duration::from_nanos(1_250_000));
+
+        config.data_bits = DataBits::Five;
+        config.parity = Parity::None;
+        config.stop_bits = StopBits::OnePointFive;
+        assert_eq!(config.char_time().unwrap(), Duration::from_nanos(781_250));
+
+        config.baud_rate = 0;
+        assert!(config.char_time().is_err());
+        config.baud_rate = 9600;
+        config.parity = Parity::Unknown;
+        assert!(config.char_time().is_err());
+    }
+
+    #[test]
+    fn test_lin_protected_id() {
+        assert_eq!(crate::lin_protected_id(0x00).unwrap(), 0x80);
+        assert_eq!(crate::lin_protected_id(0x01).unwrap(), 0xC1);
+        assert_eq!(crate::lin_protected_id(0x3C).unwrap(), 0x3C);
+        assert_eq!(crate::lin_protected_id(0x3D).unwrap(), 0x7D);
+        assert!(crate::lin_protected_id(0x40).is_err());
+    }
+
+    #[test]
+    fn test_with_config() {
+        let config = PortConfig {
+            baud_rate: 9600,
+            data_bits: DataBits::Seven,
+            flow_control: FlowControl::Hardware,
+            parity: Parity::Even,
+            stop_bits: StopBits::Two,
+        };
+        let expected = new("COM1", 9600)
+            .data_bits(DataBits::Seven)
+            .flow_control(FlowControl::Hardware)
+            .parity(Parity::Even)
+            .stop_bits(StopBits::Two);
+        assert_eq!(SerialPortBuilder::with_config("COM1", config), expected);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pty_pair() {
+        let (mut master, mut slave) = crate::pty_pair().unwrap();
+
+        master.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        slave.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        slave.write_all(b"pong").await.unwrap();
+        master.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_write_vectored() {
+        use std::io::IoSlice;
+
+        let (mut master, mut slave) = crate::pty_pair().unwrap();
+
+        let mut bufs = [
+            IoSlice::new(b"head"),
+            IoSlice::new(b""),
+            IoSlice::new(b"payload"),
+        ];
+        let mut bufs = &mut bufs[..];
+        while !bufs.is_empty() {
+            let n = master.write_vectored(bufs).await.unwrap();
+            IoSlice::advance_slices(&mut bufs, n);
+        }
+
+        let mut buf = [0; 11];
+        slave.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"headpayload");
+    }
 
     #[test]
     #[ignore = "manual"]
@@ -187,6 +1296,48 @@ mod tests {
         println!("{:#?}", available_ports())
     }
 
+    #[test]
+    fn test_filter_ports() {
+        let port = |path: &str, name: &str, port_type| PortInfo {
+            path: path.to_owned(),
+            name: name.to_owned(),
+            port_type,
+            available: None,
+        };
+        let ports = vec![
+            port("COM1", "Communications Port (COM1)", PortType::Physical),
+            port("COM3", "USB Serial Device (COM3)", PortType::Physical),
+            port("COM10", "com0com CNCA0 (COM10)", PortType::Virtual),
+            port("COM20", "", PortType::Unknown),
+        ];
+
+        let usb = filter_ports(ports.clone(), |port| {
+            port.port_type == PortType::Physical && port.name.contains("USB")
+        });
+        assert_eq!(usb, [ports[1].clone()]);
+
+        let virtual_ports = filter_ports(ports.clone(), |port| port.port_type == PortType::Virtual);
+        assert_eq!(virtual_ports, [ports[2].clone()]);
+
+        assert_eq!(filter_ports(ports.clone(), |_| true), ports);
+        assert!(filter_ports(ports, |port| port.path == "COM2").is_empty());
+    }
+
+    #[test]
+    #[ignore = "manual"]
+    fn test_available_ports_openable() {
+        println!("{:#?}", crate::available_ports_openable())
+    }
+
+    #[test]
+    #[ignore = "manual"]
+    fn test_available_ports_timeout() {
+        println!(
+            "{:#?}",
+            crate::available_ports_timeout(Duration::from_secs(1))
+        )
+    }
+
     #[test]
     #[ignore = "manual"]
     fn test_open_close() {
@@ -196,4 +1347,39 @@ mod tests {
             Err(err) => println!("{}", err),
         }
     }
+
+    #[tokio::test]
+    #[ignore = "manual"]
+    async fn test_unplug_mid_read() {
+        use tokio::io::AsyncReadExt;
+
+        // Unplug the adapter while the read waits
+        let mut serial = new("COM11", 115200).open().unwrap();
+        let mut buf = [0; 1];
+        let err = serial.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    #[ignore = "manual"]
+    async fn test_purge_on_open() {
+        // Have the peer send something before running this
+        let serial = new("COM11", 115200).open().unwrap();
+        println!(
+            "without purging: {} stale bytes",
+            serial.bytes_to_read().unwrap()
+        );
+        drop(serial);
+
+        let serial = new("COM11", 115200).purge_on_open(true).open().unwrap();
+        assert_eq!(serial.bytes_to_read().unwrap(), 0);
+    }
+
+    #[test]
+    #[ignore = "manual"]
+    fn test_effective_config() {
+        // An odd rate most UARTs can only approximate
+        let serial = new("COM11", 250_001).open().unwrap();
+        println!("{:#?}", serial.effective_config().unwrap())
+    }
 }
@@ -0,0 +1,508 @@
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{Instant, Sleep},
+};
+
+use crate::{
+    Clear, DataBits, Error, FlowControl, Parity, PortConfig, Result, SerialPort, StopBits,
+};
+
+/// An in-memory port for testing protocol code without hardware
+///
+/// Bytes written to the port are collected for the test to inspect, and the
+/// bytes it reads are the ones the test injects, through the [`MockHandle`]
+/// the port hands out:
+///
+/// ```
+/// # #[tokio::main]
+/// # async fn main() {
+/// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+///
+/// let mut port = serialport::MockPort::new("mock", 9600);
+/// let peer = port.handle();
+///
+/// port.write_all(b"ping").await.unwrap();
+/// assert_eq!(peer.take_written(), b"ping");
+///
+/// peer.receive(b"pong");
+/// let mut reply = [0; 4];
+/// port.read_exact(&mut reply).await.unwrap();
+/// assert_eq!(&reply, b"pong");
+/// # }
+/// ```
+///
+/// Writes complete at once. The line settings and control lines only record
+/// what was set, and the modem status lines read what the test set. The port
+/// has no OS handle behind it, so its raw handle is invalid.
+pub struct MockPort {
+    name: String,
+    state: Arc<Mutex<State>>,
+    /// Wakes a read waiting for received bytes to get past the latency
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+/// The test's end of a [`MockPort`]: injects what the port receives and
+/// inspects what it sent
+///
+/// Cloning a handle gives another one to the same port.
+#[derive(Clone)]
+pub struct MockHandle {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    config: PortConfig,
+    latency: Duration,
+    /// Received bytes with the time they become readable
+    received: VecDeque<(Instant, u8)>,
+    written: Vec<u8>,
+    read_waker: Option<Waker>,
+    read_error: Option<Error>,
+    disconnected: bool,
+    rts: bool,
+    dtr: bool,
+    brk: bool,
+    cts: bool,
+    dsr: bool,
+    ri: bool,
+    cd: bool,
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl MockPort {
+    /// A port named `name` with 8N1 framing at `baud_rate` and no latency
+    pub fn new(name: &str, baud_rate: u32) -> MockPort {
+        let state = State {
+            config: PortConfig {
+                baud_rate,
+                data_bits: DataBits::Eight,
+                flow_control: FlowControl::None,
+                parity: Parity::None,
+                stop_bits: StopBits::One,
+            },
+            latency: Duration::ZERO,
+            received: VecDeque::new(),
+            written: Vec::new(),
+            read_waker: None,
+            read_error: None,
+            disconnected: false,
+            rts: false,
+            dtr: false,
+            brk: false,
+            cts: false,
+            dsr: false,
+            ri: false,
+            cd: false,
+        };
+
+        MockPort {
+            name: name.to_owned(),
+            state: Arc::new(Mutex::new(state)),
+            delay: None,
+        }
+    }
+
+    /// Returns a handle for the test to drive the port with
+    pub fn handle(&self) -> MockHandle {
+        MockHandle {
+            state: self.state.clone(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+
+    /// Locks the state of a port that is still connected
+    fn connected(&self) -> Result<MutexGuard<'_, State>> {
+        let state = self.state();
+        match state.disconnected {
+            true => Err(Error::Disconnected(None)),
+            false => Ok(state),
+        }
+    }
+}
+
+impl MockHandle {
+    /// Makes the port receive `data`, readable once the latency has passed
+    pub fn receive(&self, data: &[u8]) {
+        let mut state = lock(&self.state);
+        let at = Instant::now() + state.latency;
+        state.received.extend(data.iter().map(|&b| (at, b)));
+        if let Some(waker) = state.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Returns the bytes written to the port since the last call
+    pub fn take_written(&self) -> Vec<u8> {
+        std::mem::take(&mut lock(&self.state).written)
+    }
+
+    /// Delays the bytes received from now on by `latency`, as a real line
+    /// and driver would
+    pub fn set_latency(&self, latency: Duration) {
+        lock(&self.state).latency = latency;
+    }
+
+    /// Fails the next read with `error`, e.g. [`Error::Overrun`] to simulate
+    /// a line error; the read after it carries on as usual
+    pub fn fail_next_read(&self, error: Error) {
+        let mut state = lock(&self.state);
+        state.read_error = Some(error);
+        if let Some(waker) = state.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Unplugs the device: from now on, every operation on the port fails
+    /// with [`Error::Disconnected`]
+    pub fn disconnect(&self) {
+        let mut state = lock(&self.state);
+        state.disconnected = true;
+        if let Some(waker) = state.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Returns the line settings the port was last set to
+    pub fn config(&self) -> PortConfig {
+        lock(&self.state).config
+    }
+
+    /// Returns the level the port last set RTS to
+    pub fn rts(&self) -> bool {
+        lock(&self.state).rts
+    }
+
+    /// Returns the level the port last set DTR to
+    pub fn dtr(&self) -> bool {
+        lock(&self.state).dtr
+    }
+
+    /// Returns whether the port holds a break
+    pub fn break_state(&self) -> bool {
+        lock(&self.state).brk
+    }
+
+    /// Sets the level the port reads for CTS
+    pub fn set_cts(&self, level: bool) {
+        lock(&self.state).cts = level;
+    }
+
+    /// Sets the level the port reads for DSR
+    pub fn set_dsr(&self, level: bool) {
+        lock(&self.state).dsr = level;
+    }
+
+    /// Sets the level the port reads for RI
+    pub fn set_ri(&self, level: bool) {
+        lock(&self.state).ri = level;
+    }
+
+    /// Sets the level the port reads for CD
+    pub fn set_cd(&self, level: bool) {
+        lock(&self.state).cd = level;
+    }
+}
+
+impl AsyncRead for MockPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            let mut state = lock(&this.state);
+            if let Some(err) = state.read_error.take() {
+                return Poll::Ready(Err(err.into()));
+            }
+            if state.disconnected {
+                return Poll::Ready(Err(Error::Disconnected(None).into()));
+            }
+            if buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let now = Instant::now();
+            let readable = state.received.iter().take_while(|(at, _)| *at <= now);
+            let len = readable.count().min(buf.remaining());
+            if len > 0 {
+                for (_, b) in state.received.drain(..len) {
+                    buf.put_slice(&[b]);
+                }
+                return Poll::Ready(Ok(()));
+            }
+
+            state.read_waker = Some(cx.waker().clone());
+            let Some(&(at, _)) = state.received.front() else {
+                return Poll::Pending;
+            };
+            drop(state);
+
+            // Bytes are on their way, wake up once they are readable
+            let delay = this
+                .delay
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(at)));
+            delay.as_mut().reset(at);
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl AsyncWrite for MockPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.connected()?;
+        state.written.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        drop(self.connected()?);
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[cfg(unix)]
+impl std::os::unix::io::AsRawFd for MockPort {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        -1
+    }
+}
+
+#[cfg(windows)]
+impl std::os::windows::io::AsRawHandle for MockPort {
+    fn as_raw_handle(&self) -> std::os::windows::io::RawHandle {
+        std::ptr::null_mut()
+    }
+}
+
+impl SerialPort for MockPort {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn baudrate(&self) -> Result<u32> {
+        Ok(self.connected()?.config.baud_rate)
+    }
+
+    fn data_bits(&self) -> Result<DataBits> {
+        Ok(self.connected()?.config.data_bits)
+    }
+
+    fn flow_control(&self) -> Result<FlowControl> {
+        Ok(self.connected()?.config.flow_control)
+    }
+
+    fn parity(&self) -> Result<Parity> {
+        Ok(self.connected()?.config.parity)
+    }
+
+    fn stop_bits(&self) -> Result<StopBits> {
+        Ok(self.connected()?.config.stop_bits)
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        self.connected()?.config.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> Result<()> {
+        self.connected()?.config.data_bits = data_bits;
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> Result<()> {
+        self.connected()?.config.flow_control = flow_control;
+        Ok(())
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> Result<()> {
+        self.connected()?.config.parity = parity;
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> Result<()> {
+        self.connected()?.config.stop_bits = stop_bits;
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
+        self.connected()?.rts = level;
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
+        self.connected()?.dtr = level;
+        Ok(())
+    }
+
+    fn set_break(&mut self, level: bool) -> Result<()> {
+        self.connected()?.brk = level;
+        Ok(())
+    }
+
+    fn break_state(&self) -> Result<bool> {
+        Ok(self.connected()?.brk)
+    }
+
+    fn cts(&mut self) -> Result<bool> {
+        Ok(self.connected()?.cts)
+    }
+
+    fn dsr(&mut self) -> Result<bool> {
+        Ok(self.connected()?.dsr)
+    }
+
+    fn ri(&mut self) -> Result<bool> {
+        Ok(self.connected()?.ri)
+    }
+
+    fn cd(&mut self) -> Result<bool> {
+        Ok(self.connected()?.cd)
+    }
+
+    fn bytes_to_read(&self) -> Result<u32> {
+        let state = self.connected()?;
+        let now = Instant::now();
+        let readable = state.received.iter().take_while(|(at, _)| *at <= now);
+        Ok(readable.count() as u32)
+    }
+
+    /// Always 0, as writes complete at once
+    fn bytes_to_write(&self) -> Result<u32> {
+        drop(self.connected()?);
+        Ok(0)
+    }
+
+    /// Discards the received bytes, including those still delayed by the
+    /// latency
+    fn clear(&self, buffer_to_clear: Clear) -> Result<()> {
+        let mut state = self.connected()?;
+        if matches!(
+            buffer_to_clear,
+            Clear::Input | Clear::InputSoft | Clear::All
+        ) {
+            state.received.clear();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::ErrorKind, time::Duration};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        time::Instant,
+    };
+
+    use super::MockPort;
+    use crate::{Error, SerialPort};
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let mut port = MockPort::new("mock", 9600);
+        let peer = port.handle();
+
+        port.write_all(b"ping").await.unwrap();
+        assert_eq!(peer.take_written(), b"ping");
+        assert!(peer.take_written().is_empty());
+
+        peer.receive(b"pong");
+        assert_eq!(port.bytes_to_read().unwrap(), 4);
+        let mut buf = [0; 4];
+        port.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+
+    #[tokio::test]
+    async fn test_wakes_waiting_read() {
+        let mut port = MockPort::new("mock", 9600);
+        let peer = port.handle();
+
+        let read = tokio::spawn(async move {
+            let mut buf = [0; 2];
+            port.read_exact(&mut buf).await.unwrap();
+            buf
+        });
+        tokio::task::yield_now().await;
+        peer.receive(b"ok");
+        assert_eq!(&read.await.unwrap(), b"ok");
+    }
+
+    #[tokio::test]
+    async fn test_latency() {
+        let mut port = MockPort::new("mock", 9600);
+        let peer = port.handle();
+        peer.set_latency(Duration::from_millis(20));
+
+        let start = Instant::now();
+        peer.receive(b"x");
+        assert_eq!(port.bytes_to_read().unwrap(), 0);
+
+        let mut buf = [0; 1];
+        port.read_exact(&mut buf).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let mut port = MockPort::new("mock", 9600);
+        let peer = port.handle();
+
+        peer.fail_next_read(Error::Overrun);
+        peer.receive(b"a");
+        let mut buf = [0; 1];
+        assert!(port.read(&mut buf).await.is_err());
+        assert_eq!(port.read(&mut buf).await.unwrap(), 1);
+
+        peer.disconnect();
+        let err = port.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        assert!(port.write_all(b"a").await.is_err());
+        assert!(matches!(port.set_rts(true), Err(Error::Disconnected(_))));
+    }
+
+    #[tokio::test]
+    async fn test_lines_and_config() {
+        let mut port = MockPort::new("mock", 9600);
+        let peer = port.handle();
+
+        port.set_control_lines(Some(true), Some(true)).unwrap();
+        assert!(peer.rts() && peer.dtr());
+
+        peer.set_cts(true);
+        assert!(port.cts().unwrap());
+        assert!(!port.dsr().unwrap());
+
+        port.set_baud_rate(115200).unwrap();
+        assert_eq!(peer.config().baud_rate, 115200);
+        assert_eq!(port.effective_config().unwrap(), peer.config());
+    }
+}
@@ -1,39 +1,51 @@
 use std::io;
 
-use crate::{Error, ErrorKind};
+use crate::Error;
 
 #[cfg(all(target_os = "linux", not(target_env = "musl"), feature = "libudev"))]
 impl From<libudev::Error> for Error {
     fn from(e: libudev::Error) -> Error {
         use libudev::ErrorKind as K;
-        let kind = match e.kind() {
-            K::NoMem => ErrorKind::Unknown,
-            K::InvalidInput => ErrorKind::InvalidInput,
-            K::Io(a) => ErrorKind::Io(a),
-        };
-        Error::new(kind, e.description())
+        match e.kind() {
+            K::NoMem => Error::Io(io::Error::new(io::ErrorKind::OutOfMemory, e.description())),
+            K::InvalidInput => Error::InvalidArgument(e.description().to_owned()),
+            K::Io(kind) => Error::Io(io::Error::new(kind, e.description())),
+        }
     }
 }
 
+/// Calls on an open port, see [`from_io`]
 impl From<nix::Error> for Error {
     fn from(e: nix::Error) -> Error {
-        use io::ErrorKind as IO;
-        use nix::errno::Errno as E;
-        use ErrorKind as K;
-        let kind = match e {
-            E::ETIMEDOUT => K::Io(IO::TimedOut),
-            E::ECONNABORTED => K::Io(IO::ConnectionAborted),
-            E::ECONNRESET => K::Io(IO::ConnectionReset),
-            E::ECONNREFUSED => K::Io(IO::ConnectionRefused),
-            E::ENOTCONN => K::Io(IO::NotConnected),
-            E::EADDRINUSE => K::Io(IO::AddrInUse),
-            E::EADDRNOTAVAIL => K::Io(IO::AddrNotAvailable),
-            E::EAGAIN => K::Io(IO::WouldBlock),
-            E::EINTR => K::Io(IO::Interrupted),
-            E::EACCES => K::Io(IO::PermissionDenied),
-            E::ENOENT => K::Io(IO::NotFound),
-            _ => K::Unknown,
-        };
-        Error::new(kind, e.desc())
+        from_io(e)
+    }
+}
+
+/// Map an error from a call on an open port to [`Error::Disconnected`] if it
+/// means the device is gone
+///
+/// Opening a device that is missing fails with the same codes, which mean
+/// no more than that there, so `TTYPort::open` keeps them as [`Error::Io`].
+pub(crate) fn from_io(e: nix::Error) -> Error {
+    match e {
+        // What the tty layer reports once the device behind it is gone
+        nix::Error::ENXIO | nix::Error::ENODEV | nix::Error::EIO => {
+            Error::Disconnected(Some(io::Error::from(e)))
+        }
+        e => Error::Io(io::Error::from(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_io() {
+        let err = from_io(nix::Error::EIO);
+        assert!(matches!(err, Error::Disconnected(Some(_))));
+        assert_eq!(err.raw_os_error(), Some(nix::libc::EIO));
+
+        assert!(matches!(from_io(nix::Error::EINVAL), Error::Io(_)));
     }
 }
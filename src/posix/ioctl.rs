@@ -12,7 +12,10 @@ mod raw {
 
     ioctl_none_bad!(tiocexcl, libc::TIOCEXCL);
     ioctl_none_bad!(tiocnxcl, libc::TIOCNXCL);
+    #[cfg(target_os = "linux")]
+    ioctl_read_bad!(tiocgexcl, libc::TIOCGEXCL, libc::c_int);
     ioctl_read_bad!(tiocmget, libc::TIOCMGET, libc::c_int);
+    ioctl_write_ptr_bad!(tiocmset, libc::TIOCMSET, libc::c_int);
     ioctl_none_bad!(tiocsbrk, libc::TIOCSBRK);
     ioctl_none_bad!(tioccbrk, libc::TIOCCBRK);
 
@@ -116,6 +119,14 @@ pub fn tiocnxcl(fd: RawFd) -> Result<()> {
         .map_err(|e| e.into())
 }
 
+#[cfg(target_os = "linux")]
+pub fn tiocgexcl(fd: RawFd) -> Result<bool> {
+    let mut exclusive: libc::c_int = 0;
+    unsafe { raw::tiocgexcl(fd, &mut exclusive) }
+        .map(|_| exclusive != 0)
+        .map_err(|e| e.into())
+}
+
 pub fn tiocmget(fd: RawFd) -> Result<SerialLines> {
     let mut status: libc::c_int = 0;
     unsafe { raw::tiocmget(fd, &mut status) }
@@ -123,6 +134,13 @@ pub fn tiocmget(fd: RawFd) -> Result<SerialLines> {
         .map_err(|e| e.into())
 }
 
+pub fn tiocmset(fd: RawFd, status: SerialLines) -> Result<()> {
+    let bits = status.bits() as libc::c_int;
+    unsafe { raw::tiocmset(fd, &bits) }
+        .map(|_| ())
+        .map_err(|e| e.into())
+}
+
 pub fn tiocsbrk(fd: RawFd) -> Result<()> {
     unsafe { raw::tiocsbrk(fd) }
         .map(|_| ())
@@ -1,7 +1,7 @@
 // A set of helper functions for working with the `termios` and `termios2` structs
 use cfg_if::cfg_if;
 
-use crate::{DataBits, FlowControl, Parity, Result, StopBits};
+use crate::{DataBits, Error, FlowControl, FlowControlState, Parity, Result, StopBits};
 use nix::libc;
 
 use std::os::unix::prelude::*;
@@ -145,7 +145,87 @@ pub(crate) fn set_termios(fd: RawFd, termios: &Termios) -> Result<()> {
     crate::posix::ioctl::tcsets2(fd, termios)
 }
 
-pub(crate) fn set_parity(termios: &mut Termios, parity: Parity) {
+/// Read the settings back, failing if the driver did not apply all of them
+pub(crate) fn verify_termios(fd: RawFd, expected: &Termios) -> Result<()> {
+    let actual = get_termios(fd)?;
+
+    let fields = [
+        ("c_cflag", expected.c_cflag as u64, actual.c_cflag as u64),
+        ("c_iflag", expected.c_iflag as u64, actual.c_iflag as u64),
+        #[cfg(any(
+            target_os = "android",
+            all(
+                target_os = "linux",
+                not(any(
+                    target_env = "musl",
+                    target_arch = "powerpc",
+                    target_arch = "powerpc64"
+                ))
+            )
+        ))]
+        ("BaudRate", expected.c_ospeed as u64, actual.c_ospeed as u64),
+    ];
+
+    match fields
+        .into_iter()
+        .find(|(_, expected, actual)| expected != actual)
+    {
+        Some((field, expected, actual)) => Err(Error::InvalidArgument(format!(
+            "{field} {expected:#x} was not applied by the driver, it reports {actual:#x}"
+        ))),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn data_bits(termios: &Termios) -> DataBits {
+    match termios.c_cflag & libc::CSIZE {
+        libc::CS8 => DataBits::Eight,
+        libc::CS7 => DataBits::Seven,
+        libc::CS6 => DataBits::Six,
+        libc::CS5 => DataBits::Five,
+        _ => DataBits::Unknown,
+    }
+}
+
+pub(crate) fn flow_control(termios: &Termios) -> FlowControl {
+    flow_control_state(termios).mode()
+}
+
+pub(crate) fn flow_control_state(termios: &Termios) -> FlowControlState {
+    FlowControlState {
+        rts_cts: termios.c_cflag & libc::CRTSCTS == libc::CRTSCTS,
+        dsr_dtr: false,
+        xon_xoff: termios.c_iflag & (libc::IXON | libc::IXOFF) != 0,
+    }
+}
+
+pub(crate) fn parity(termios: &Termios) -> Parity {
+    if termios.c_cflag & libc::PARENB == 0 {
+        return Parity::None;
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    if termios.c_cflag & libc::CMSPAR == libc::CMSPAR {
+        return match termios.c_cflag & libc::PARODD {
+            0 => Parity::Space,
+            _ => Parity::Mark,
+        };
+    }
+
+    match termios.c_cflag & libc::PARODD {
+        0 => Parity::Even,
+        _ => Parity::Odd,
+    }
+}
+
+pub(crate) fn stop_bits(termios: &Termios) -> StopBits {
+    match termios.c_cflag & libc::CSTOPB {
+        0 => StopBits::One,
+        _ => StopBits::Two,
+    }
+}
+
+pub(crate) fn set_parity(termios: &mut Termios, parity: Parity) -> Result<()> {
     match parity {
         Parity::None => {
             termios.c_cflag &= !(libc::PARENB | libc::PARODD);
@@ -163,10 +243,32 @@ pub(crate) fn set_parity(termios: &mut Termios, parity: Parity) {
             termios.c_iflag |= libc::INPCK;
             termios.c_iflag &= !libc::IGNPAR;
         }
+        // Sticky parity: with CMSPAR set, PARODD selects a parity bit of 1
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        Parity::Mark => {
+            termios.c_cflag |= libc::PARENB | libc::CMSPAR | libc::PARODD;
+            termios.c_iflag |= libc::INPCK;
+            termios.c_iflag &= !libc::IGNPAR;
+        }
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        Parity::Space => {
+            termios.c_cflag &= !libc::PARODD;
+            termios.c_cflag |= libc::PARENB | libc::CMSPAR;
+            termios.c_iflag |= libc::INPCK;
+            termios.c_iflag &= !libc::IGNPAR;
+        }
+        parity => return Err(Error::InvalidArgument(format!("Parity::{parity:?}"))),
     };
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    if !matches!(parity, Parity::Mark | Parity::Space) {
+        termios.c_cflag &= !libc::CMSPAR;
+    }
+
+    Ok(())
 }
 
-pub(crate) fn set_flow_control(termios: &mut Termios, flow_control: FlowControl) {
+pub(crate) fn set_flow_control(termios: &mut Termios, flow_control: FlowControl) -> Result<()> {
     match flow_control {
         FlowControl::None => {
             termios.c_iflag &= !(libc::IXON | libc::IXOFF);
@@ -180,26 +282,36 @@ pub(crate) fn set_flow_control(termios: &mut Termios, flow_control: FlowControl)
             termios.c_iflag &= !(libc::IXON | libc::IXOFF);
             termios.c_cflag |= libc::CRTSCTS;
         }
+        FlowControl::Unknown => {
+            return Err(Error::InvalidArgument("FlowControl::Unknown".to_owned()));
+        }
     };
+    Ok(())
 }
 
-pub(crate) fn set_data_bits(termios: &mut Termios, data_bits: DataBits) {
+pub(crate) fn set_data_bits(termios: &mut Termios, data_bits: DataBits) -> Result<()> {
     let size = match data_bits {
         DataBits::Five => libc::CS5,
         DataBits::Six => libc::CS6,
         DataBits::Seven => libc::CS7,
         DataBits::Eight => libc::CS8,
+        DataBits::Unknown => {
+            return Err(Error::InvalidArgument("DataBits::Unknown".to_owned()));
+        }
     };
 
     termios.c_cflag &= !libc::CSIZE;
     termios.c_cflag |= size;
+    Ok(())
 }
 
-pub(crate) fn set_stop_bits(termios: &mut Termios, stop_bits: StopBits) {
+pub(crate) fn set_stop_bits(termios: &mut Termios, stop_bits: StopBits) -> Result<()> {
     match stop_bits {
         StopBits::One => termios.c_cflag &= !libc::CSTOPB,
         StopBits::Two => termios.c_cflag |= libc::CSTOPB,
+        stop_bits => return Err(Error::InvalidArgument(format!("StopBits::{stop_bits:?}"))),
     };
+    Ok(())
 }
 
 #[cfg(any(
@@ -243,8 +355,6 @@ pub(crate) fn set_baud_rate(termios: &mut Termios, baud_rate: u32) -> Result<()>
     )
 ))]
 pub(crate) fn set_baud_rate(termios: &mut Termios, baud_rate: u32) -> Result<()> {
-    use crate::{Error, ErrorKind};
-
     use self::libc::{
         B1000000, B1152000, B1500000, B2000000, B2500000, B3000000, B3500000, B4000000, B460800,
         B500000, B576000, B921600,
@@ -285,7 +395,11 @@ pub(crate) fn set_baud_rate(termios: &mut Termios, baud_rate: u32) -> Result<()>
         3_000_000 => B3000000,
         3_500_000 => B3500000,
         4_000_000 => B4000000,
-        _ => return Err(Error::new(ErrorKind::InvalidInput, "Unsupported baud rate")),
+        _ => {
+            return Err(Error::InvalidArgument(format!(
+                "baud rate {baud_rate} is not supported"
+            )));
+        }
     };
     let res = unsafe { libc::cfsetspeed(termios, baud_rate) };
     nix::errno::Errno::result(res)?;
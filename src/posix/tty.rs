@@ -1,36 +1,29 @@
-use std::mem::MaybeUninit;
+use std::mem::{ManuallyDrop, MaybeUninit};
 use std::os::unix::prelude::*;
 use std::path::Path;
-use std::time::{Duration, Instant};
-use std::{io, mem};
-
-use nix::fcntl::{fcntl, OFlag};
-use nix::{libc, unistd};
-
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll, ready};
+#[cfg(test)]
+use std::time::Duration;
+use std::{io, ptr};
+
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::libc;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+#[cfg(test)]
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::posix::error;
 use crate::posix::ioctl::{self, SerialLines};
-use crate::posix::termios;
+use crate::posix::termios::{self, Termios};
 use crate::{
-    ClearBuffer, DataBits, Error, ErrorKind, FlowControl, Parity, Result, SerialPort,
-    SerialPortBuilder, StopBits,
+    Access, Clear, DataBits, Error, FlowControl, FlowControlState, Parity, PortConfig, Result,
+    SerialPort, SerialPortBuilder, ShareMode, StopBits,
 };
 
-/// Convenience method for removing exclusive access from
-/// a fd and closing it.
-fn close(fd: RawFd) {
-    // remove exclusive access
-    let _ = ioctl::tiocnxcl(fd);
-
-    // On Linux and BSD, we don't need to worry about return
-    // type as EBADF means the fd was never open or is already closed
-    //
-    // Linux and BSD guarantee that for any other error code the
-    // fd is already closed, though MacOSX does not.
-    //
-    // close() also should never be retried, and the error code
-    // in most cases in purely informative
-    let _ = unistd::close(fd);
-}
-
 /// A serial port implementation for POSIX TTY ports
 ///
 /// The port will be closed when the value is dropped. This struct
@@ -38,10 +31,16 @@ fn close(fd: RawFd) {
 /// Instead, use the cross-platform `serialport::new()`. Example:
 ///
 /// ```no_run
+/// # #[tokio::main]
+/// # async fn main() {
 /// let mut port = serialport::new("/dev/ttyS0", 115200).open().expect("Unable to open");
 /// # let _ = &mut port;
+/// # }
 /// ```
 ///
+/// The port is driven by the tokio reactor, so it must be opened from within
+/// a tokio runtime.
+///
 /// Note: on macOS, when connecting to a pseudo-terminal (`pty` opened via
 /// `posix_openpt`), the `baud_rate` should be set to 0; this will be used to
 /// explicitly _skip_ an attempt to set the baud rate of the file descriptor
@@ -50,19 +49,24 @@ fn close(fd: RawFd) {
 /// ```no_run
 /// use serialport::{TTYPort, SerialPort};
 ///
+/// # #[tokio::main]
+/// # async fn main() {
 /// let (mut master, mut slave) = TTYPort::pair().expect("Unable to create ptty pair");
 /// # let _ = &mut master;
 /// # let _ = &mut slave;
 /// // ... elsewhere
-/// let mut port = TTYPort::open(&serialport::new(slave.name().unwrap(), 0)).expect("Unable to open");
+/// let mut port = TTYPort::open(&serialport::new(&slave.name(), 0)).expect("Unable to open");
 /// # let _ = &mut port;
+/// # }
 /// ```
-#[derive(Debug)]
 pub struct TTYPort {
-    fd: RawFd,
-    timeout: Duration,
+    fd: AsyncFd<OwnedFd>,
     exclusive: bool,
     port_name: Option<String>,
+    access: Access,
+    verify_config: bool,
+    /// Whether a break is asserted, shared with the clones of the port
+    break_state: Arc<AtomicBool>,
     #[cfg(any(target_os = "ios", target_os = "macos"))]
     baud_rate: u32,
 }
@@ -76,62 +80,98 @@ pub enum BreakDuration {
     Arbitrary(std::num::NonZeroI32),
 }
 
-/// Wrapper for RawFd to assure that it's properly closed,
-/// even if the enclosing function exits early.
-///
-/// This is similar to the (nightly-only) std::os::unix::io::OwnedFd.
-struct OwnedFd(RawFd);
-
-impl Drop for OwnedFd {
-    fn drop(&mut self) {
-        close(self.0);
-    }
-}
-
-impl OwnedFd {
-    fn into_raw(self) -> RawFd {
-        let fd = self.0;
-        mem::forget(self);
-        fd
-    }
-}
-
 impl TTYPort {
     /// Opens a TTY device as a serial port.
     ///
     /// `path` should be the path to a TTY device, e.g., `/dev/ttyS0`.
     ///
-    /// Ports are opened in exclusive mode by default. If this is undesirable
-    /// behavior, use `TTYPort::set_exclusive(false)`.
+    /// Ports are opened in exclusive mode unless the builder asks for another
+    /// [`ShareMode`]. This can be changed later with `TTYPort::set_exclusive`.
     ///
     /// If the port settings differ from the default settings, characters received
     /// before the new settings become active may be garbled. To remove those
-    /// from the receive buffer, call `TTYPort::clear(ClearBuffer::Input)`.
+    /// from the receive buffer, call `TTYPort::clear(Clear::Input)`.
+    ///
+    /// The device is opened with `O_NONBLOCK | O_NOCTTY`, so opening a modem
+    /// port does not wait for its carrier and the port never becomes the
+    /// controlling terminal. `O_NONBLOCK` is kept afterwards: the port is
+    /// driven by the tokio reactor, so reads and writes must fail with
+    /// `EAGAIN` rather than block. They then wait for the fd to become ready,
+    /// so callers never see `EAGAIN`. `CLOCAL` is set so the carrier is
+    /// ignored from then on as well, unless the builder asks to follow it
+    /// with `ignore_carrier(false)`. With `preserve_settings(true)` none of
+    /// this is done and the device is left as it was configured.
     ///
     /// ## Errors
     ///
-    /// * `NoDevice` if the device could not be opened. This could indicate that
-    ///    the device is already in use.
-    /// * `InvalidInput` if `path` is not a valid device name.
+    /// * `Busy` if another process holds the port exclusively.
+    /// * `InvalidArgument` if a setting is not supported on this platform.
     /// * `Io` for any other error while opening or initializing the device.
     pub fn open(builder: &SerialPortBuilder) -> Result<TTYPort> {
-        use nix::fcntl::FcntlArg::F_SETFL;
-        use nix::libc::{cfmakeraw, tcgetattr, tcsetattr};
-
         let path = Path::new(&builder.path);
-        let fd = OwnedFd(nix::fcntl::open(
+        let access = match builder.access {
+            Access::ReadWrite => OFlag::O_RDWR,
+            Access::ReadOnly => OFlag::O_RDONLY,
+            Access::WriteOnly => OFlag::O_WRONLY,
+        };
+        let fd = match nix::fcntl::open(
             path,
-            OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_NONBLOCK | OFlag::O_CLOEXEC,
+            access | OFlag::O_NOCTTY | OFlag::O_NONBLOCK | OFlag::O_CLOEXEC,
             nix::sys::stat::Mode::empty(),
-        )?);
+        ) {
+            Ok(fd) => fd,
+            Err(e @ nix::errno::Errno::EBUSY) => return Err(Error::Busy(Some(e.into()))),
+            Err(e) => return Err(Error::Io(e.into())),
+        };
 
         // Try to claim exclusive access to the port. This is performed even
         // if the port will later be set as non-exclusive, in order to respect
         // other applications that may have an exclusive port lock.
-        ioctl::tiocexcl(fd.0)?;
+        ioctl::tiocexcl(fd.as_raw_fd())?;
+
+        let termios = match builder.preserve_settings {
+            false => Some(Self::configure(fd.as_raw_fd(), builder)?),
+            true => None,
+        };
+        #[cfg(any(target_os = "ios", target_os = "macos"))]
+        let baud_rate = match termios {
+            Some(_) => builder.baudrate,
+            None => unsafe { libc::cfgetospeed(&termios::get_termios(fd.as_raw_fd())?) as u32 },
+        };
+
+        let mut port = TTYPort {
+            fd: AsyncFd::new(fd)?,
+            exclusive: true,
+            port_name: Some(builder.path.clone()),
+            access: builder.access,
+            verify_config: builder.verify_config,
+            break_state: Arc::default(),
+            #[cfg(any(target_os = "ios", target_os = "macos"))]
+            baud_rate,
+        };
+        if let Some(termios) = &termios {
+            port.write_termios(termios)?;
+        }
+
+        // There is no finer sharing control than on or off
+        if builder.share_mode != ShareMode::Exclusive {
+            port.set_exclusive(false)?;
+        }
+
+        if builder.purge_on_open {
+            port.clear(Clear::All)?;
+        }
+
+        Ok(port)
+    }
+
+    /// Puts the device into raw mode and builds the settings the builder
+    /// asks for, to be applied once the port is set up
+    fn configure(fd: RawFd, builder: &SerialPortBuilder) -> Result<Termios> {
+        use nix::libc::{cfmakeraw, tcgetattr, tcsetattr};
 
         let mut termios = MaybeUninit::uninit();
-        nix::errno::Errno::result(unsafe { tcgetattr(fd.0, termios.as_mut_ptr()) })?;
+        nix::errno::Errno::result(unsafe { tcgetattr(fd, termios.as_mut_ptr()) })?;
         let mut termios = unsafe { termios.assume_init() };
 
         // setup TTY for binary serial port access
@@ -143,11 +183,11 @@ impl TTYPort {
         unsafe { cfmakeraw(&mut termios) };
 
         // write settings to TTY
-        unsafe { tcsetattr(fd.0, libc::TCSANOW, &termios) };
+        unsafe { tcsetattr(fd, libc::TCSANOW, &termios) };
 
         // Read back settings from port and confirm they were applied correctly
         let mut actual_termios = MaybeUninit::uninit();
-        unsafe { tcgetattr(fd.0, actual_termios.as_mut_ptr()) };
+        unsafe { tcgetattr(fd, actual_termios.as_mut_ptr()) };
         let actual_termios = unsafe { actual_termios.assume_init() };
 
         if actual_termios.c_iflag != termios.c_iflag
@@ -155,66 +195,54 @@ impl TTYPort {
             || actual_termios.c_lflag != termios.c_lflag
             || actual_termios.c_cflag != termios.c_cflag
         {
-            return Err(Error::new(
-                ErrorKind::Unknown,
-                "Settings did not apply correctly",
-            ));
+            return Err(io::Error::other("Settings did not apply correctly").into());
         };
 
         #[cfg(any(target_os = "ios", target_os = "macos"))]
-        if builder.baud_rate > 0 {
-            unsafe { libc::tcflush(fd.0, libc::TCIOFLUSH) };
+        if builder.baudrate > 0 {
+            unsafe { libc::tcflush(fd, libc::TCIOFLUSH) };
         }
 
-        // clear O_NONBLOCK flag
-        fcntl(fd.0, F_SETFL(nix::fcntl::OFlag::empty()))?;
-
         // Configure the low-level port settings
-        let mut termios = termios::get_termios(fd.0)?;
-        termios::set_parity(&mut termios, builder.parity);
-        termios::set_flow_control(&mut termios, builder.flow_control);
-        termios::set_data_bits(&mut termios, builder.data_bits);
-        termios::set_stop_bits(&mut termios, builder.stop_bits);
+        let mut termios = termios::get_termios(fd)?;
+        termios::set_parity(&mut termios, builder.parity)?;
+        termios::set_flow_control(&mut termios, builder.flow_control)?;
+        termios::set_data_bits(&mut termios, builder.data_bits)?;
+        termios::set_stop_bits(&mut termios, builder.stop_bits)?;
         #[cfg(not(any(target_os = "ios", target_os = "macos")))]
-        termios::set_baud_rate(&mut termios, builder.baud_rate)?;
-        #[cfg(any(target_os = "ios", target_os = "macos"))]
-        termios::set_termios(fd.0, &termios, builder.baud_rate)?;
-        #[cfg(not(any(target_os = "ios", target_os = "macos")))]
-        termios::set_termios(fd.0, &termios)?;
-
-        // Return the final port object
-        let mut port = TTYPort {
-            fd: fd.into_raw(),
-            timeout: builder.timeout,
-            exclusive: true,
-            port_name: Some(builder.path.clone()),
-            #[cfg(any(target_os = "ios", target_os = "macos"))]
-            baud_rate: builder.baud_rate,
-        };
-
-        // Ignore setting DTR for pseudo terminals. This might be indicated by baud_rate == 0, but
-        // as this is not always the case, just try on best-effort.
-        if builder.baud_rate > 0 {
-            if let Some(dtr) = builder.dtr_on_open {
-                let _ = port.write_data_terminal_ready(dtr);
-            }
+        termios::set_baud_rate(&mut termios, builder.baudrate)?;
+        if !builder.ignore_carrier {
+            termios.c_cflag &= !libc::CLOCAL;
         }
 
-        Ok(port)
+        Ok(termios)
     }
 
     /// Returns the exclusivity of the port
     ///
     /// If a port is exclusive, then trying to open the same device path again
     /// will fail.
-    pub fn exclusive(&self) -> bool {
-        self.exclusive
+    ///
+    /// On Linux the state is read from the device, so a change made through
+    /// another handle to it, e.g. a clone, is seen too. Elsewhere this is the
+    /// state last set through this port.
+    pub fn exclusive(&self) -> Result<bool> {
+        #[cfg(target_os = "linux")]
+        return ioctl::tiocgexcl(self.as_raw_fd());
+
+        #[cfg(not(target_os = "linux"))]
+        Ok(self.exclusive)
     }
 
     /// Sets the exclusivity of the port
     ///
     /// If a port is exclusive, then trying to open the same device path again
-    /// will fail.
+    /// will fail. This allows, for example, relaxing exclusivity while a
+    /// monitoring tool attaches and locking the port again afterwards.
+    /// Processes with `CAP_SYS_ADMIN` may open the port regardless.
+    ///
+    /// Windows has no equivalent: there the [`ShareMode`] is fixed when the
+    /// port is opened.
     ///
     /// See the man pages for the tiocexcl and tiocnxcl ioctl's for more details.
     ///
@@ -223,9 +251,9 @@ impl TTYPort {
     /// * `Io` for any error while setting exclusivity for the port.
     pub fn set_exclusive(&mut self, exclusive: bool) -> Result<()> {
         let setting_result = if exclusive {
-            ioctl::tiocexcl(self.fd)
+            ioctl::tiocexcl(self.as_raw_fd())
         } else {
-            ioctl::tiocnxcl(self.fd)
+            ioctl::tiocnxcl(self.as_raw_fd())
         };
 
         setting_result?;
@@ -235,14 +263,28 @@ impl TTYPort {
 
     fn set_pin(&mut self, pin: ioctl::SerialLines, level: bool) -> Result<()> {
         if level {
-            ioctl::tiocmbis(self.fd, pin)
+            ioctl::tiocmbis(self.as_raw_fd(), pin)
         } else {
-            ioctl::tiocmbic(self.fd, pin)
+            ioctl::tiocmbic(self.as_raw_fd(), pin)
         }
     }
 
     fn read_pin(&mut self, pin: ioctl::SerialLines) -> Result<bool> {
-        ioctl::tiocmget(self.fd).map(|pins| pins.contains(pin))
+        ioctl::tiocmget(self.as_raw_fd()).map(|pins| pins.contains(pin))
+    }
+
+    /// Applies `termios`, and reads it back if the builder asked to verify
+    /// the configuration
+    fn write_termios(&self, termios: &Termios) -> Result<()> {
+        #[cfg(any(target_os = "ios", target_os = "macos"))]
+        termios::set_termios(self.as_raw_fd(), termios, self.baud_rate)?;
+        #[cfg(not(any(target_os = "ios", target_os = "macos")))]
+        termios::set_termios(self.as_raw_fd(), termios)?;
+
+        if self.verify_config {
+            termios::verify_termios(self.as_raw_fd(), termios)?;
+        }
+        Ok(())
     }
 
     /// Create a pair of pseudo serial terminals
@@ -262,14 +304,17 @@ impl TTYPort {
     /// ```
     /// use serialport::TTYPort;
     ///
+    /// # #[tokio::main]
+    /// # async fn main() {
     /// let (mut master, mut slave) = TTYPort::pair().unwrap();
     ///
     /// # let _ = &mut master;
     /// # let _ = &mut slave;
+    /// # }
     /// ```
     pub fn pair() -> Result<(Self, Self)> {
         // Open the next free pty.
-        let next_pty_fd = nix::pty::posix_openpt(nix::fcntl::OFlag::O_RDWR)?;
+        let next_pty_fd = nix::pty::posix_openpt(OFlag::O_RDWR | OFlag::O_NOCTTY)?;
 
         // Grant access to the associated slave pty
         nix::pty::grantpt(&next_pty_fd)?;
@@ -299,31 +344,25 @@ impl TTYPort {
         let baud_rate = 9600;
         let fd = nix::fcntl::open(
             Path::new(&ptty_name),
-            OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_NONBLOCK,
+            OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_NONBLOCK | OFlag::O_CLOEXEC,
             nix::sys::stat::Mode::empty(),
         )?;
 
         // Set the port to a raw state. Using these ports will not work without this.
         let mut termios = MaybeUninit::uninit();
-        let res = unsafe { crate::posix::tty::libc::tcgetattr(fd, termios.as_mut_ptr()) };
-        if let Err(e) = nix::errno::Errno::result(res) {
-            close(fd);
-            return Err(e.into());
-        }
+        let res = unsafe { libc::tcgetattr(fd.as_raw_fd(), termios.as_mut_ptr()) };
+        nix::errno::Errno::result(res)?;
         let mut termios = unsafe { termios.assume_init() };
-        unsafe { crate::posix::tty::libc::cfmakeraw(&mut termios) };
-        unsafe { crate::posix::tty::libc::tcsetattr(fd, libc::TCSANOW, &termios) };
-
-        fcntl(
-            fd,
-            nix::fcntl::FcntlArg::F_SETFL(nix::fcntl::OFlag::empty()),
-        )?;
+        unsafe { libc::cfmakeraw(&mut termios) };
+        unsafe { libc::tcsetattr(fd.as_raw_fd(), libc::TCSANOW, &termios) };
 
         let slave_tty = TTYPort {
-            fd,
-            timeout: Duration::from_millis(100),
-            exclusive: true,
+            fd: AsyncFd::new(fd)?,
+            exclusive: false,
             port_name: Some(ptty_name),
+            access: Access::ReadWrite,
+            verify_config: false,
+            break_state: Arc::default(),
             #[cfg(any(target_os = "ios", target_os = "macos"))]
             baud_rate,
         };
@@ -331,11 +370,15 @@ impl TTYPort {
         // Manually construct the master port here because the
         // `tcgetattr()` doesn't work on Mac, Solaris, and maybe other
         // BSDs when used on the master port.
+        let master_fd = unsafe { OwnedFd::from_raw_fd(next_pty_fd.into_raw_fd()) };
+        set_nonblocking(&master_fd)?;
         let master_tty = TTYPort {
-            fd: next_pty_fd.into_raw_fd(),
-            timeout: Duration::from_millis(100),
-            exclusive: true,
+            fd: AsyncFd::new(master_fd)?,
+            exclusive: false,
             port_name: None,
+            access: Access::ReadWrite,
+            verify_config: false,
+            break_state: Arc::default(),
             #[cfg(any(target_os = "ios", target_os = "macos"))]
             baud_rate,
         };
@@ -343,36 +386,121 @@ impl TTYPort {
         Ok((master_tty, slave_tty))
     }
 
+    /// Wraps an already open TTY file descriptor, e.g. one passed by systemd
+    /// socket activation or received over a unix socket
+    ///
+    /// The descriptor is switched to non-blocking and raw mode; its baud rate
+    /// and other settings are left as they are. `path` is only reported by
+    /// [`SerialPort::name`], it is not opened.
+    ///
+    /// ## Safety
+    ///
+    /// `fd` must be an open file descriptor that nothing else owns or closes.
+    /// The returned port takes ownership of it and closes it when dropped,
+    /// including when this function fails.
+    ///
+    /// ## Errors
+    ///
+    /// * `InvalidArgument` if `fd` is not a TTY.
+    /// * `Io` for any other error while initializing the device.
+    pub unsafe fn from_raw_fd(fd: RawFd, path: String) -> Result<TTYPort> {
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        if !nix::unistd::isatty(&fd)? {
+            return Err(Error::InvalidArgument(format!("{} is not a TTY", path)));
+        }
+
+        set_nonblocking(&fd)?;
+        let access = access_mode(&fd)?;
+
+        let mut termios = MaybeUninit::uninit();
+        let res = unsafe { libc::tcgetattr(fd.as_raw_fd(), termios.as_mut_ptr()) };
+        nix::errno::Errno::result(res)?;
+        let mut termios = unsafe { termios.assume_init() };
+        termios.c_cflag |= libc::CREAD | libc::CLOCAL;
+        unsafe { libc::cfmakeraw(&mut termios) };
+        let res = unsafe { libc::tcsetattr(fd.as_raw_fd(), libc::TCSANOW, &termios) };
+        nix::errno::Errno::result(res)?;
+
+        Ok(TTYPort {
+            fd: AsyncFd::new(fd)?,
+            exclusive: false,
+            port_name: Some(path),
+            access,
+            verify_config: false,
+            break_state: Arc::default(),
+            #[cfg(any(target_os = "ios", target_os = "macos"))]
+            baud_rate: unsafe { libc::cfgetospeed(&termios) } as u32,
+        })
+    }
+
     /// Sends 0-valued bits over the port for a set duration
     pub fn send_break(&self, duration: BreakDuration) -> Result<()> {
         match duration {
-            BreakDuration::Short => nix::sys::termios::tcsendbreak(self.fd, 0),
-            BreakDuration::Arbitrary(n) => nix::sys::termios::tcsendbreak(self.fd, n.get()),
+            BreakDuration::Short => nix::sys::termios::tcsendbreak(self.fd.get_ref(), 0),
+            BreakDuration::Arbitrary(n) => {
+                nix::sys::termios::tcsendbreak(self.fd.get_ref(), n.get())
+            }
         }
         .map_err(|e| e.into())
     }
 
-    /// Attempts to clone the `SerialPort`. This allow you to write and read simultaneously from the
-    /// same serial connection. Please note that if you want a real asynchronous serial port you
-    /// should look at [mio-serial](https://crates.io/crates/mio-serial) or
-    /// [tokio-serial](https://crates.io/crates/tokio-serial).
+    /// Runs `f` until `cancel` completes, failing then with
+    /// [`Error::Cancelled`]
     ///
-    /// Also, you must be very careful when changing the settings of a cloned `SerialPort` : since
-    /// the settings are cached on a per object basis, trying to modify them from two different
-    /// objects can cause some nasty behavior.
+    /// `cancel` can be any future, e.g. `token.cancelled()` of a
+    /// `tokio_util` `CancellationToken`; one that is already complete wins
+    /// over `f`. Reads and writes only wait for the port to become ready, so
+    /// no I/O is left in flight once `f` is abandoned.
+    pub async fn with_cancel<F, T>(&mut self, cancel: impl Future<Output = ()>, f: F) -> Result<T>
+    where
+        F: AsyncFnOnce(&mut Self) -> Result<T>,
+    {
+        tokio::select! {
+            biased;
+            _ = cancel => Err(Error::Cancelled),
+            result = f(self) => result,
+        }
+    }
+
+    /// Returns the name of the kernel driver backing the port, e.g.
+    /// `ftdi_sio`, `cp210x` or `cdc_acm`, for telling driver quirks apart in
+    /// bug reports
+    ///
+    /// Ports without a device behind them, such as pseudo terminals, have no
+    /// driver to report. Only supported on Linux.
+    pub fn provider(&self) -> Result<String> {
+        #[cfg(target_os = "linux")]
+        return match &self.port_name {
+            Some(path) => crate::posix::enumerate::driver_name(path),
+            None => Err(Error::InvalidArgument("port has no device path".into())),
+        };
+
+        #[cfg(not(target_os = "linux"))]
+        Err(Error::NotImplemented)
+    }
+
+    /// Attempts to clone the `TTYPort`, giving another handle to the same
+    /// device.
     ///
-    /// This is the same as `SerialPort::try_clone()` but returns the concrete type instead.
+    /// Be careful when changing the settings of a cloned port: they apply to
+    /// the device, so they affect every handle to it.
     ///
     /// # Errors
     ///
     /// This function returns an error if the serial port couldn't be cloned.
     pub fn try_clone_native(&self) -> Result<TTYPort> {
-        let fd_cloned: i32 = fcntl(self.fd, nix::fcntl::F_DUPFD_CLOEXEC(self.fd))?;
+        let fd_cloned = fcntl(
+            self.fd.get_ref(),
+            FcntlArg::F_DUPFD_CLOEXEC(self.as_raw_fd()),
+        )?;
         Ok(TTYPort {
-            fd: fd_cloned,
+            fd: AsyncFd::new(unsafe { OwnedFd::from_raw_fd(fd_cloned) })?,
             exclusive: self.exclusive,
             port_name: self.port_name.clone(),
-            timeout: self.timeout,
+            access: self.access,
+            verify_config: self.verify_config,
+            break_state: self.break_state.clone(),
             #[cfg(any(target_os = "ios", target_os = "macos"))]
             baud_rate: self.baud_rate,
         })
@@ -381,101 +509,163 @@ impl TTYPort {
 
 impl Drop for TTYPort {
     fn drop(&mut self) {
-        close(self.fd);
+        // Give up exclusive access; the fd itself is closed by `OwnedFd`
+        if self.exclusive {
+            let _ = ioctl::tiocnxcl(self.as_raw_fd());
+        }
+    }
+}
+
+/// Shows the path and, read back from the device, the line settings; the
+/// file descriptor is left out
+impl std::fmt::Debug for TTYPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("TTYPort");
+        debug.field("path", &self.port_name);
+        // The device may be gone, which leaves just the path
+        if let Ok(config) = self.effective_config() {
+            debug.field("config", &config);
+        }
+        debug.finish_non_exhaustive()
     }
 }
 
 impl AsRawFd for TTYPort {
     fn as_raw_fd(&self) -> RawFd {
-        self.fd
+        self.fd.as_raw_fd()
     }
 }
 
 impl IntoRawFd for TTYPort {
     fn into_raw_fd(self) -> RawFd {
-        // Pull just the file descriptor out. We also prevent the destructor
-        // from being run by calling `mem::forget`. If we didn't do this, the
-        // port would be closed, which would make `into_raw_fd` unusable.
-        let TTYPort { fd, .. } = self;
-        mem::forget(self);
-        fd
+        // Pull just the file descriptor out. The destructor is skipped, so the
+        // exclusive access is handed over along with the fd.
+        let mut port = ManuallyDrop::new(self);
+        let fd = unsafe { ptr::read(&port.fd) };
+        unsafe { ptr::drop_in_place(&mut port.port_name) };
+        unsafe { ptr::drop_in_place(&mut port.break_state) };
+        fd.into_inner().into_raw_fd()
     }
 }
 
-/// Get the baud speed for a port from its file descriptor
-#[cfg(any(target_os = "ios", target_os = "macos"))]
-fn get_termios_speed(fd: RawFd) -> u32 {
-    let mut termios = MaybeUninit::uninit();
-    let res = unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) };
-    nix::errno::Errno::result(res).expect("Failed to get termios data");
-    let termios = unsafe { termios.assume_init() };
-    assert_eq!(termios.c_ospeed, termios.c_ispeed);
-    termios.c_ospeed as u32
-}
-
-impl FromRawFd for TTYPort {
-    unsafe fn from_raw_fd(fd: RawFd) -> Self {
-        TTYPort {
-            fd,
-            timeout: Duration::from_millis(100),
-            exclusive: ioctl::tiocexcl(fd).is_ok(),
-            // It is not trivial to get the file path corresponding to a file descriptor.
-            // We'll punt on it and set it to `None` here.
-            port_name: None,
-            // It's not guaranteed that the baud rate in the `termios` struct is correct, as
-            // setting an arbitrary baud rate via the `iossiospeed` ioctl overrides that value,
-            // but extract that value anyways as a best-guess of the actual baud rate.
-            #[cfg(any(target_os = "ios", target_os = "macos"))]
-            baud_rate: get_termios_speed(fd),
-        }
+/// Whether the port at `path` can be opened right now, see
+/// [`crate::available_ports_openable`]
+pub(crate) fn is_openable(path: &str) -> bool {
+    let Ok(fd) = nix::fcntl::open(
+        path,
+        OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_NONBLOCK | OFlag::O_CLOEXEC,
+        nix::sys::stat::Mode::empty(),
+    ) else {
+        return false;
+    };
+
+    // Privileged processes get past an exclusive lock, so look for one
+    #[cfg(target_os = "linux")]
+    if ioctl::tiocgexcl(fd.as_raw_fd()).unwrap_or(false) {
+        return false;
     }
+
+    drop(fd);
+    true
+}
+
+/// Adds `O_NONBLOCK` to the file status flags of `fd`, which the reactor
+/// driving the port relies on, see [`TTYPort::open`]
+fn set_nonblocking(fd: &OwnedFd) -> Result<()> {
+    let flags = OFlag::from_bits_retain(fcntl(fd, FcntlArg::F_GETFL)?);
+    fcntl(fd, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+    Ok(())
+}
+
+/// The directions `fd` was opened for
+fn access_mode(fd: &OwnedFd) -> Result<Access> {
+    let flags = OFlag::from_bits_retain(fcntl(fd, FcntlArg::F_GETFL)?);
+    Ok(match flags & OFlag::O_ACCMODE {
+        OFlag::O_RDONLY => Access::ReadOnly,
+        OFlag::O_WRONLY => Access::WriteOnly,
+        _ => Access::ReadWrite,
+    })
+}
+
+/// Goes through [`Error`] so a device that is gone reads as
+/// [`Error::Disconnected`]; `EAGAIN` stays a `WouldBlock` for `try_io`
+fn io_error(e: nix::Error) -> io::Error {
+    error::from_io(e).into()
 }
 
-impl io::Read for TTYPort {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        if let Err(e) = super::poll::wait_read_fd(self.fd, self.timeout) {
-            return Err(io::Error::from(Error::from(e)));
+impl AsyncRead for TTYPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.access.check_read()?;
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
         }
 
-        nix::unistd::read(self.fd, buf).map_err(|e| io::Error::from(Error::from(e)))
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|fd| nix::unistd::read(fd.get_ref(), unfilled).map_err(io_error)) {
+                // With no data a read fails with `EAGAIN`, so reading nothing
+                // at all means the tty was hung up
+                Ok(Ok(0)) => return Poll::Ready(Err(Error::Disconnected(None).into())),
+                Ok(result) => return Poll::Ready(result.map(|n| buf.advance(n))),
+                Err(_would_block) => continue,
+            }
+        }
     }
 }
 
-impl io::Write for TTYPort {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        if let Err(e) = super::poll::wait_write_fd(self.fd, self.timeout) {
-            return Err(io::Error::from(Error::from(e)));
+impl AsyncWrite for TTYPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.access.check_write()?;
+        loop {
+            let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+            match guard.try_io(|fd| nix::unistd::write(fd.get_ref(), buf).map_err(io_error)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
         }
-
-        nix::unistd::write(self.fd, buf).map_err(|e| io::Error::from(Error::from(e)))
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        let timeout = Instant::now() + self.timeout;
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.access.check_write()?;
         loop {
-            return match nix::sys::termios::tcdrain(self.fd) {
-                Ok(_) => Ok(()),
-                Err(nix::errno::Errno::EINTR) => {
-                    // Retry flushing. But only up to the ports timeout for not retrying
-                    // indefinitely in case that it gets interrupted again.
-                    if Instant::now() < timeout {
-                        continue;
-                    } else {
-                        Err(io::Error::new(
-                            io::ErrorKind::TimedOut,
-                            "timeout for retrying flush reached",
-                        ))
-                    }
-                }
-                Err(_) => Err(io::Error::new(io::ErrorKind::Other, "flush failed")),
-            };
+            let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+            match guard.try_io(|fd| nix::sys::uio::writev(fd.get_ref(), bufs).map_err(io_error)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
         }
     }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    /// Written bytes go straight to the kernel, so there is nothing to flush
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
 }
 
 impl SerialPort for TTYPort {
-    fn name(&self) -> Option<String> {
-        self.port_name.clone()
+    fn name(&self) -> String {
+        self.port_name.clone().unwrap_or_default()
     }
 
     /// Returns the port's baud rate
@@ -493,8 +683,8 @@ impl SerialPort for TTYPort {
             ))
         )
     ))]
-    fn baud_rate(&self) -> Result<u32> {
-        let termios2 = ioctl::tcgets2(self.fd)?;
+    fn baudrate(&self) -> Result<u32> {
+        let termios2 = ioctl::tcgets2(self.as_raw_fd())?;
 
         assert!(termios2.c_ospeed == termios2.c_ispeed);
 
@@ -511,8 +701,8 @@ impl SerialPort for TTYPort {
         target_os = "netbsd",
         target_os = "openbsd"
     ))]
-    fn baud_rate(&self) -> Result<u32> {
-        let termios = termios::get_termios(self.fd)?;
+    fn baudrate(&self) -> Result<u32> {
+        let termios = termios::get_termios(self.as_raw_fd())?;
 
         let ospeed = unsafe { libc::cfgetospeed(&termios) };
         let ispeed = unsafe { libc::cfgetispeed(&termios) };
@@ -527,7 +717,7 @@ impl SerialPort for TTYPort {
     /// On some platforms this will be the actual device baud rate, which may differ from the
     /// desired baud rate.
     #[cfg(any(target_os = "ios", target_os = "macos"))]
-    fn baud_rate(&self) -> Result<u32> {
+    fn baudrate(&self) -> Result<u32> {
         Ok(self.baud_rate)
     }
 
@@ -543,7 +733,7 @@ impl SerialPort for TTYPort {
             target_arch = "powerpc64"
         )
     ))]
-    fn baud_rate(&self) -> Result<u32> {
+    fn baudrate(&self) -> Result<u32> {
         use self::libc::{
             B1000000, B1152000, B1500000, B2000000, B2500000, B3000000, B3500000, B4000000,
             B460800, B500000, B576000, B921600,
@@ -553,7 +743,7 @@ impl SerialPort for TTYPort {
             B4800, B50, B57600, B600, B75, B9600,
         };
 
-        let termios = termios::get_termios(self.fd)?;
+        let termios = termios::get_termios(self.as_raw_fd())?;
         let ospeed = unsafe { libc::cfgetospeed(&termios) };
         let ispeed = unsafe { libc::cfgetispeed(&termios) };
 
@@ -597,54 +787,27 @@ impl SerialPort for TTYPort {
     }
 
     fn data_bits(&self) -> Result<DataBits> {
-        let termios = termios::get_termios(self.fd)?;
-        match termios.c_cflag & libc::CSIZE {
-            libc::CS8 => Ok(DataBits::Eight),
-            libc::CS7 => Ok(DataBits::Seven),
-            libc::CS6 => Ok(DataBits::Six),
-            libc::CS5 => Ok(DataBits::Five),
-            _ => Err(Error::new(
-                ErrorKind::Unknown,
-                "Invalid data bits setting encountered",
-            )),
-        }
+        Ok(termios::data_bits(&termios::get_termios(self.as_raw_fd())?))
     }
 
     fn flow_control(&self) -> Result<FlowControl> {
-        let termios = termios::get_termios(self.fd)?;
-        if termios.c_cflag & libc::CRTSCTS == libc::CRTSCTS {
-            Ok(FlowControl::Hardware)
-        } else if termios.c_iflag & (libc::IXON | libc::IXOFF) == (libc::IXON | libc::IXOFF) {
-            Ok(FlowControl::Software)
-        } else {
-            Ok(FlowControl::None)
-        }
+        Ok(termios::flow_control(&termios::get_termios(
+            self.as_raw_fd(),
+        )?))
     }
 
-    fn parity(&self) -> Result<Parity> {
-        let termios = termios::get_termios(self.fd)?;
-        if termios.c_cflag & libc::PARENB == libc::PARENB {
-            if termios.c_cflag & libc::PARODD == libc::PARODD {
-                Ok(Parity::Odd)
-            } else {
-                Ok(Parity::Even)
-            }
-        } else {
-            Ok(Parity::None)
-        }
+    fn flow_control_state(&self) -> Result<FlowControlState> {
+        Ok(termios::flow_control_state(&termios::get_termios(
+            self.as_raw_fd(),
+        )?))
     }
 
-    fn stop_bits(&self) -> Result<StopBits> {
-        let termios = termios::get_termios(self.fd)?;
-        if termios.c_cflag & libc::CSTOPB == libc::CSTOPB {
-            Ok(StopBits::Two)
-        } else {
-            Ok(StopBits::One)
-        }
+    fn parity(&self) -> Result<Parity> {
+        Ok(termios::parity(&termios::get_termios(self.as_raw_fd())?))
     }
 
-    fn timeout(&self) -> Duration {
-        self.timeout
+    fn stop_bits(&self) -> Result<StopBits> {
+        Ok(termios::stop_bits(&termios::get_termios(self.as_raw_fd())?))
     }
 
     #[cfg(any(
@@ -656,124 +819,161 @@ impl SerialPort for TTYPort {
         target_os = "linux"
     ))]
     fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
-        let mut termios = termios::get_termios(self.fd)?;
+        let mut termios = termios::get_termios(self.as_raw_fd())?;
         termios::set_baud_rate(&mut termios, baud_rate)?;
-        termios::set_termios(self.fd, &termios)
+        self.write_termios(&termios)
     }
 
     // Mac OS needs special logic for setting arbitrary baud rates.
     #[cfg(any(target_os = "ios", target_os = "macos"))]
     fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
-        ioctl::iossiospeed(self.fd, &(baud_rate as libc::speed_t))?;
+        ioctl::iossiospeed(self.as_raw_fd(), &(baud_rate as libc::speed_t))?;
         self.baud_rate = baud_rate;
         Ok(())
     }
 
-    fn set_flow_control(&mut self, flow_control: FlowControl) -> Result<()> {
-        let mut termios = termios::get_termios(self.fd)?;
-        termios::set_flow_control(&mut termios, flow_control);
-        #[cfg(any(target_os = "ios", target_os = "macos"))]
-        return termios::set_termios(self.fd, &termios, self.baud_rate);
-        #[cfg(not(any(target_os = "ios", target_os = "macos")))]
-        return termios::set_termios(self.fd, &termios);
+    fn to_builder(&self) -> Result<SerialPortBuilder> {
+        Ok(
+            SerialPortBuilder::with_config(&self.name(), self.effective_config()?)
+                .access(self.access),
+        )
     }
 
-    fn set_parity(&mut self, parity: Parity) -> Result<()> {
-        let mut termios = termios::get_termios(self.fd)?;
-        termios::set_parity(&mut termios, parity);
-        #[cfg(any(target_os = "ios", target_os = "macos"))]
-        return termios::set_termios(self.fd, &termios, self.baud_rate);
+    fn set_config_atomic(&mut self, config: &PortConfig) -> Result<()> {
+        let saved = termios::get_termios(self.as_raw_fd())?;
+        let mut termios = saved;
         #[cfg(not(any(target_os = "ios", target_os = "macos")))]
-        return termios::set_termios(self.fd, &termios);
+        termios::set_baud_rate(&mut termios, config.baud_rate)?;
+        termios::set_data_bits(&mut termios, config.data_bits)?;
+        termios::set_flow_control(&mut termios, config.flow_control)?;
+        termios::set_parity(&mut termios, config.parity)?;
+        termios::set_stop_bits(&mut termios, config.stop_bits)?;
+
+        // The baud rate is set separately from the termios on macOS
+        #[cfg(any(target_os = "ios", target_os = "macos"))]
+        let saved_baud_rate = std::mem::replace(&mut self.baud_rate, config.baud_rate);
+
+        self.write_termios(&termios).inspect_err(|_| {
+            #[cfg(any(target_os = "ios", target_os = "macos"))]
+            {
+                self.baud_rate = saved_baud_rate;
+            }
+            let _ = self.write_termios(&saved);
+        })
     }
 
     fn set_data_bits(&mut self, data_bits: DataBits) -> Result<()> {
-        let mut termios = termios::get_termios(self.fd)?;
-        termios::set_data_bits(&mut termios, data_bits);
-        #[cfg(any(target_os = "ios", target_os = "macos"))]
-        return termios::set_termios(self.fd, &termios, self.baud_rate);
-        #[cfg(not(any(target_os = "ios", target_os = "macos")))]
-        return termios::set_termios(self.fd, &termios);
+        let mut termios = termios::get_termios(self.as_raw_fd())?;
+        termios::set_data_bits(&mut termios, data_bits)?;
+        self.write_termios(&termios)
     }
 
-    fn set_stop_bits(&mut self, stop_bits: StopBits) -> Result<()> {
-        let mut termios = termios::get_termios(self.fd)?;
-        termios::set_stop_bits(&mut termios, stop_bits);
-        #[cfg(any(target_os = "ios", target_os = "macos"))]
-        return termios::set_termios(self.fd, &termios, self.baud_rate);
-        #[cfg(not(any(target_os = "ios", target_os = "macos")))]
-        return termios::set_termios(self.fd, &termios);
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> Result<()> {
+        let mut termios = termios::get_termios(self.as_raw_fd())?;
+        termios::set_flow_control(&mut termios, flow_control)?;
+        self.write_termios(&termios)
     }
 
-    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
-        self.timeout = timeout;
-        Ok(())
+    fn set_parity(&mut self, parity: Parity) -> Result<()> {
+        let mut termios = termios::get_termios(self.as_raw_fd())?;
+        termios::set_parity(&mut termios, parity)?;
+        self.write_termios(&termios)
     }
 
-    fn write_request_to_send(&mut self, level: bool) -> Result<()> {
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> Result<()> {
+        let mut termios = termios::get_termios(self.as_raw_fd())?;
+        termios::set_stop_bits(&mut termios, stop_bits)?;
+        self.write_termios(&termios)
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<()> {
         self.set_pin(SerialLines::REQUEST_TO_SEND, level)
     }
 
-    fn write_data_terminal_ready(&mut self, level: bool) -> Result<()> {
+    fn set_dtr(&mut self, level: bool) -> Result<()> {
         self.set_pin(SerialLines::DATA_TERMINAL_READY, level)
     }
 
-    fn read_clear_to_send(&mut self) -> Result<bool> {
+    fn set_control_lines(&mut self, rts: Option<bool>, dtr: Option<bool>) -> Result<()> {
+        let mut lines = ioctl::tiocmget(self.as_raw_fd())?;
+        if let Some(rts) = rts {
+            lines.set(SerialLines::REQUEST_TO_SEND, rts);
+        }
+        if let Some(dtr) = dtr {
+            lines.set(SerialLines::DATA_TERMINAL_READY, dtr);
+        }
+        ioctl::tiocmset(self.as_raw_fd(), lines)
+    }
+
+    fn set_break(&mut self, level: bool) -> Result<()> {
+        if level {
+            ioctl::tiocsbrk(self.as_raw_fd())?;
+        } else {
+            ioctl::tioccbrk(self.as_raw_fd())?;
+        }
+        self.break_state.store(level, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn break_state(&self) -> Result<bool> {
+        Ok(self.break_state.load(Ordering::Relaxed))
+    }
+
+    fn set_mark(&mut self, level: bool) -> Result<()> {
+        use nix::sys::termios::{FlowArg, tcflow};
+
+        if level {
+            self.set_break(false)?;
+            tcflow(self.fd.get_ref(), FlowArg::TCOOFF)?;
+        } else {
+            tcflow(self.fd.get_ref(), FlowArg::TCOON)?;
+        }
+        Ok(())
+    }
+
+    fn cts(&mut self) -> Result<bool> {
         self.read_pin(SerialLines::CLEAR_TO_SEND)
     }
 
-    fn read_data_set_ready(&mut self) -> Result<bool> {
+    fn dsr(&mut self) -> Result<bool> {
         self.read_pin(SerialLines::DATA_SET_READY)
     }
 
-    fn read_ring_indicator(&mut self) -> Result<bool> {
+    fn ri(&mut self) -> Result<bool> {
         self.read_pin(SerialLines::RING)
     }
 
-    fn read_carrier_detect(&mut self) -> Result<bool> {
+    fn cd(&mut self) -> Result<bool> {
         self.read_pin(SerialLines::DATA_CARRIER_DETECT)
     }
 
     fn bytes_to_read(&self) -> Result<u32> {
-        ioctl::fionread(self.fd)
+        ioctl::fionread(self.as_raw_fd())
     }
 
     fn bytes_to_write(&self) -> Result<u32> {
-        ioctl::tiocoutq(self.fd)
+        ioctl::tiocoutq(self.as_raw_fd())
     }
 
-    fn clear(&self, buffer_to_clear: ClearBuffer) -> Result<()> {
+    fn clear(&self, buffer_to_clear: Clear) -> Result<()> {
         let buffer_id = match buffer_to_clear {
-            ClearBuffer::Input => libc::TCIFLUSH,
-            ClearBuffer::Output => libc::TCOFLUSH,
-            ClearBuffer::All => libc::TCIOFLUSH,
+            // Flushing never aborts a read or write, so the soft variants are
+            // the same
+            Clear::Input | Clear::InputSoft => libc::TCIFLUSH,
+            Clear::Output | Clear::OutputSoft => libc::TCOFLUSH,
+            Clear::All => libc::TCIOFLUSH,
         };
 
-        let res = unsafe { nix::libc::tcflush(self.fd, buffer_id) };
+        let res = unsafe { nix::libc::tcflush(self.as_raw_fd(), buffer_id) };
 
         nix::errno::Errno::result(res)
             .map(|_| ())
             .map_err(|e| e.into())
     }
-
-    fn try_clone(&self) -> Result<Box<dyn SerialPort>> {
-        match self.try_clone_native() {
-            Ok(p) => Ok(Box::new(p)),
-            Err(e) => Err(e),
-        }
-    }
-
-    fn set_break(&self) -> Result<()> {
-        ioctl::tiocsbrk(self.fd)
-    }
-
-    fn clear_break(&self) -> Result<()> {
-        ioctl::tioccbrk(self.fd)
-    }
 }
 
-#[test]
-fn test_ttyport_into_raw_fd() {
+#[tokio::test]
+async fn test_ttyport_into_raw_fd() {
     // `master` must be used here as Dropping it causes slave to be deleted by the OS.
     // TODO: Convert this to a statement-level attribute once
     //       https://github.com/rust-lang/rust/issues/15701 is on stable.
@@ -782,21 +982,325 @@ fn test_ttyport_into_raw_fd() {
     let (master, slave) = TTYPort::pair().expect("Unable to create ptty pair");
 
     // First test with the master
-    let master_fd = master.into_raw_fd();
+    let master_fd = unsafe { OwnedFd::from_raw_fd(master.into_raw_fd()) };
     let mut termios = MaybeUninit::uninit();
-    let res = unsafe { nix::libc::tcgetattr(master_fd, termios.as_mut_ptr()) };
+    let res = unsafe { nix::libc::tcgetattr(master_fd.as_raw_fd(), termios.as_mut_ptr()) };
     if res != 0 {
-        close(master_fd);
         panic!("tcgetattr on the master port failed");
     }
 
     // And then the slave
-    let slave_fd = slave.into_raw_fd();
-    let res = unsafe { nix::libc::tcgetattr(slave_fd, termios.as_mut_ptr()) };
+    let slave_fd = unsafe { OwnedFd::from_raw_fd(slave.into_raw_fd()) };
+    let res = unsafe { nix::libc::tcgetattr(slave_fd.as_raw_fd(), termios.as_mut_ptr()) };
     if res != 0 {
-        close(slave_fd);
         panic!("tcgetattr on the master port failed");
     }
-    close(master_fd);
-    close(slave_fd);
+}
+
+#[tokio::test]
+async fn test_ttyport_from_raw_fd() {
+    let pty = nix::pty::openpty(None, None).expect("Unable to open a pty");
+    let path = nix::unistd::ttyname(&pty.slave).expect("Unable to name the pty");
+    let path = path.to_string_lossy().into_owned();
+
+    let port = unsafe { TTYPort::from_raw_fd(pty.slave.into_raw_fd(), path.clone()) }
+        .expect("Unable to wrap the pty");
+    assert_eq!(port.name(), path);
+
+    let (read, write) = nix::unistd::pipe().unwrap();
+    drop(write);
+    let result = unsafe { TTYPort::from_raw_fd(read.into_raw_fd(), "pipe".to_owned()) };
+    assert!(matches!(result, Err(Error::InvalidArgument(_))));
+}
+
+#[tokio::test]
+async fn test_ttyport_open_nonblocking() {
+    let (_master, slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    let path = slave.name();
+    drop(slave);
+
+    let port = TTYPort::open(&crate::new(&path, 9600)).expect("Unable to open the pty");
+    let flags = OFlag::from_bits_retain(fcntl(port.fd.get_ref(), FcntlArg::F_GETFL).unwrap());
+    assert!(flags.contains(OFlag::O_NONBLOCK));
+}
+
+#[tokio::test]
+async fn test_ttyport_exclusive() {
+    let (_master, slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    let path = slave.name();
+    drop(slave);
+
+    let mut port = TTYPort::open(&crate::new(&path, 9600)).expect("Unable to open the pty");
+    assert!(port.exclusive().unwrap());
+
+    port.set_exclusive(false).unwrap();
+    assert!(!port.exclusive().unwrap());
+
+    port.set_exclusive(true).unwrap();
+    assert!(port.exclusive().unwrap());
+}
+
+#[tokio::test]
+async fn test_ttyport_transact() {
+    let (mut master, mut slave) = TTYPort::pair().expect("Unable to create ptty pair");
+
+    // A stale reply from an earlier exchange must not be taken for this one
+    master.write_all(b"stale").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let peer = tokio::spawn(async move {
+        let mut request = [0; 4];
+        master.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"ping");
+        // The reply arrives over several reads
+        master.write_all(b"po").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        master.write_all(b"ng").await.unwrap();
+        master
+    });
+
+    let reply = slave
+        .transact(b"ping", 4, Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(reply, b"pong");
+
+    let _master = peer.await.unwrap();
+    let err = slave
+        .transact(b"ping", 4, Duration::from_millis(50))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Incomplete(0)));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_ttyport_provider() {
+    let (_master, slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    assert!(slave.provider().is_err());
+}
+
+#[tokio::test]
+async fn test_ttyport_set_config_atomic() {
+    let (_master, mut slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    slave.verify_config = true;
+    let saved = slave.effective_config().unwrap();
+
+    // A pty keeps its 8N framing, so it takes the flow control and stop bits
+    // but drops the parity, which fails the check after they were written
+    let rejected = PortConfig {
+        flow_control: FlowControl::Hardware,
+        parity: Parity::Even,
+        stop_bits: StopBits::Two,
+        ..saved
+    };
+    assert!(slave.set_config_atomic(&rejected).is_err());
+    assert_eq!(slave.effective_config().unwrap(), saved);
+
+    let accepted = PortConfig {
+        flow_control: FlowControl::Hardware,
+        stop_bits: StopBits::Two,
+        ..saved
+    };
+    slave.set_config_atomic(&accepted).unwrap();
+    assert_eq!(slave.effective_config().unwrap(), accepted);
+}
+
+#[tokio::test]
+async fn test_ttyport_with_cancel() {
+    let (mut master, mut slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    let mut buf = [0; 4];
+
+    // Nothing arrives, so only the signal ends the read
+    let result = slave
+        .with_cancel(std::future::ready(()), async |port| {
+            Ok(port.read_exact(&mut buf).await?)
+        })
+        .await;
+    assert!(matches!(result, Err(Error::Cancelled)));
+
+    master.write_all(b"data").await.unwrap();
+    slave
+        .with_cancel(std::future::pending(), async |port| {
+            Ok(port.read_exact(&mut buf).await?)
+        })
+        .await
+        .unwrap();
+    assert_eq!(&buf, b"data");
+
+    let builder = crate::new(&slave.name(), 9600);
+    let result = builder.open_async_with_cancel(std::future::ready(())).await;
+    assert!(matches!(result, Err(Error::Cancelled)));
+}
+
+#[tokio::test]
+async fn test_ttyport_purge_on_open() {
+    let (mut master, slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    let path = slave.name();
+
+    master.write_all(b"stale").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let port = TTYPort::open(&crate::new(&path, 9600).purge_on_open(true)).unwrap();
+    assert_eq!(port.bytes_to_read().unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_ttyport_break_state() {
+    let (_master, mut slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    let clone = slave.try_clone_native().unwrap();
+    assert!(!slave.break_state().unwrap());
+
+    slave.set_break(true).unwrap();
+    assert!(slave.break_state().unwrap() && clone.break_state().unwrap());
+    slave.set_break(false).unwrap();
+    assert!(!slave.break_state().unwrap() && !clone.break_state().unwrap());
+}
+
+#[tokio::test]
+async fn test_is_openable() {
+    let (_master, slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    let path = slave.name();
+    assert!(is_openable(&path));
+
+    let port = TTYPort::open(&crate::new(&path, 9600)).unwrap();
+    assert!(!is_openable(&path));
+    drop(port);
+    assert!(is_openable(&path));
+}
+
+#[tokio::test]
+async fn test_ttyport_flow_control_state() {
+    let (_master, mut slave) = TTYPort::pair().expect("Unable to create ptty pair");
+
+    // Input flow control alone is still software flow control
+    let mut termios = termios::get_termios(slave.as_raw_fd()).unwrap();
+    termios.c_iflag |= libc::IXOFF;
+    slave.write_termios(&termios).unwrap();
+    let state = slave.flow_control_state().unwrap();
+    assert!(state.xon_xoff && !state.rts_cts);
+    assert_eq!(slave.flow_control().unwrap(), FlowControl::Software);
+
+    slave.set_flow_control(FlowControl::None).unwrap();
+    assert_eq!(
+        slave.flow_control_state().unwrap(),
+        FlowControlState::default()
+    );
+}
+
+#[tokio::test]
+async fn test_ttyport_access() {
+    let (mut master, slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    let path = slave.name();
+
+    let mut reader = crate::new(&path, 9600)
+        .share_mode(ShareMode::ReadWrite)
+        .access(Access::ReadOnly)
+        .open()
+        .unwrap();
+    let err = reader.write_all(b"x").await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+    master.write_all(b"sniffed").await.unwrap();
+    let mut buf = [0; 7];
+    reader.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"sniffed");
+
+    // A port taken over from a write-only fd knows it cannot read
+    let writer = crate::new(&path, 9600)
+        .share_mode(ShareMode::ReadWrite)
+        .access(Access::WriteOnly)
+        .open()
+        .unwrap();
+    let mut writer = unsafe { TTYPort::from_raw_fd(writer.into_raw_fd(), path) }.unwrap();
+    let err = writer.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+}
+
+#[tokio::test]
+async fn test_ttyport_debug() {
+    let (master, slave) = TTYPort::pair().expect("Unable to create ptty pair");
+
+    let debug = format!("{slave:?}");
+    assert!(debug.starts_with(&format!("TTYPort {{ path: Some({:?})", slave.name())));
+    assert!(debug.contains("baud_rate"));
+    assert!(!debug.contains("fd"));
+
+    // Without a path and with settings that cannot be read, it still formats
+    let debug = format!("{master:?}");
+    assert!(debug.starts_with("TTYPort { path: None"));
+}
+
+#[tokio::test]
+async fn test_ttyport_ignore_carrier() {
+    let (_master, slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    let path = slave.name();
+    let clocal = |port: &TTYPort| {
+        let termios = termios::get_termios(port.as_raw_fd()).unwrap();
+        termios.c_cflag & libc::CLOCAL != 0
+    };
+
+    let port = crate::new(&path, 9600).open().unwrap();
+    assert!(clocal(&port));
+    drop(port);
+
+    let port = crate::new(&path, 9600)
+        .ignore_carrier(false)
+        .open()
+        .unwrap();
+    assert!(!clocal(&port));
+}
+
+#[tokio::test]
+async fn test_ttyport_set_mark() {
+    let (mut master, mut slave) = TTYPort::pair().expect("Unable to create ptty pair");
+
+    slave.set_break(true).unwrap();
+    slave.set_mark(true).unwrap();
+    assert!(!slave.break_state().unwrap());
+
+    // Output waits while the line is held at mark
+    let write = tokio::time::timeout(Duration::from_millis(50), slave.write_all(b"held"));
+    assert!(write.await.is_err());
+
+    slave.set_mark(false).unwrap();
+    slave.write_all(b"idle").await.unwrap();
+    let mut buf = [0; 4];
+    tokio::time::timeout(Duration::from_secs(1), master.read_exact(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&buf, b"idle");
+}
+
+#[tokio::test]
+async fn test_ttyport_preserve_settings() {
+    let (_master, mut slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    let path = slave.name();
+    slave.set_baud_rate(4800).unwrap();
+
+    let port = crate::new(&path, 9600)
+        .preserve_settings(true)
+        .open()
+        .unwrap();
+    assert_eq!(port.baudrate().unwrap(), 4800);
+}
+
+#[tokio::test]
+async fn test_ttyport_to_builder() {
+    let (_master, slave) = TTYPort::pair().expect("Unable to create ptty pair");
+    let builder = crate::new(&slave.name(), 19200).access(Access::ReadOnly);
+
+    let port = builder.clone().open().unwrap();
+    assert_eq!(port.to_builder().unwrap(), builder);
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+#[tokio::test]
+async fn test_ttyport_set_baud_rate_effective() {
+    let (_master, mut slave) = TTYPort::pair().expect("Unable to create ptty pair");
+
+    // Not one of the standard rates
+    let effective = slave.set_baud_rate_effective(250_000).unwrap();
+    assert_eq!(effective, 250_000);
+    assert_eq!(slave.baudrate().unwrap(), effective);
 }
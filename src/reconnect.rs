@@ -0,0 +1,322 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{Error, NativePort, PortInfo, Result, SerialPort, SerialPortBuilder, available_ports};
+
+type Reopen = Pin<Box<dyn Future<Output = NativePort> + Send>>;
+
+/// A port that reopens itself after its device was unplugged
+///
+/// When a read or write fails with [`Error::Disconnected`], the port is closed
+/// and reopened from the builder it was opened with, retrying every
+/// [`ReconnectingPort::backoff`] until that succeeds. I/O then resumes on the
+/// new port. Data in flight when the device went away is lost, as are
+/// settings made on the old port after it was opened.
+///
+/// While the port is gone, reads and writes wait for it to come back, or fail
+/// with [`Error::Disconnected`] if [`ReconnectingPort::wait_for_reconnect`]
+/// is turned off. Reconnecting only makes progress while the port is polled.
+pub struct ReconnectingPort {
+    builder: SerialPortBuilder,
+    name: Option<String>,
+    backoff: Duration,
+    wait: bool,
+    port: Option<NativePort>,
+    reopen: Option<Reopen>,
+}
+
+impl ReconnectingPort {
+    /// Opens the port described by `builder`
+    pub async fn open(builder: SerialPortBuilder) -> Result<ReconnectingPort> {
+        let port = builder.clone().open_async().await?;
+        Ok(ReconnectingPort {
+            builder,
+            name: None,
+            backoff: Duration::from_secs(1),
+            wait: true,
+            port: Some(port),
+            reopen: None,
+        })
+    }
+
+    /// How long to wait before each attempt to reopen the port, one second
+    /// by default
+    #[must_use]
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Finds the port to reopen by the [`crate::PortInfo::name`] it is listed
+    /// under, for devices that come back under another path
+    ///
+    /// The `(COMn)` suffix of Windows friendly names is ignored on both
+    /// sides, as it changes along with the port number; `name` may be given
+    /// with or without it. If several ports match, the first listed is
+    /// taken, and if none does, the last path is tried.
+    #[must_use]
+    pub fn follow_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Whether reads and writes wait for the port to be reopened (the
+    /// default) or fail with [`Error::Disconnected`] meanwhile
+    #[must_use]
+    pub fn wait_for_reconnect(mut self, wait: bool) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Whether the port is open, as opposed to waiting to be reopened
+    pub fn is_connected(&self) -> bool {
+        self.port.is_some()
+    }
+
+    /// The port currently open, if any
+    pub fn get_ref(&self) -> Option<&NativePort> {
+        self.port.as_ref()
+    }
+
+    /// The port currently open, if any, e.g. to change its settings
+    pub fn get_mut(&mut self) -> Option<&mut NativePort> {
+        self.port.as_mut()
+    }
+
+    /// Runs `io` on the open port, reopening it first if it is gone
+    fn poll_io<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut io: impl FnMut(Pin<&mut NativePort>, &mut Context<'_>) -> Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            if let Some(port) = self.port.as_mut() {
+                match ready!(io(Pin::new(port), cx)) {
+                    Err(e) if is_disconnected(&e) => self.port = None,
+                    result => return Poll::Ready(result),
+                }
+            }
+
+            let reopen = self.reopen.get_or_insert_with(|| {
+                Box::pin(reopen(
+                    self.builder.clone(),
+                    self.name.clone(),
+                    self.backoff,
+                ))
+            });
+            match reopen.as_mut().poll(cx) {
+                Poll::Ready(port) => {
+                    self.builder.path = port.name();
+                    self.port = Some(port);
+                    self.reopen = None;
+                }
+                Poll::Pending if self.wait => return Poll::Pending,
+                Poll::Pending => return Poll::Ready(Err(Error::Disconnected(None).into())),
+            }
+        }
+    }
+}
+
+fn is_disconnected(err: &io::Error) -> bool {
+    err.get_ref()
+        .and_then(|e| e.downcast_ref::<Error>())
+        .is_some_and(|e| matches!(e, Error::Disconnected(_)))
+}
+
+/// Keeps trying to open the port until it succeeds
+async fn reopen(
+    mut builder: SerialPortBuilder,
+    name: Option<String>,
+    backoff: Duration,
+) -> NativePort {
+    loop {
+        tokio::time::sleep(backoff).await;
+
+        if let Some(name) = &name {
+            let found = tokio::task::spawn_blocking(available_ports).await;
+            if let Ok(Ok(ports)) = found
+                && let Some(info) = find_port(ports, name)
+            {
+                builder.path = info.path;
+            }
+        }
+
+        if let Ok(port) = builder.clone().open_async().await {
+            return port;
+        }
+    }
+}
+
+/// The port listed under `name`, ignoring the `(COMn)` suffixes
+fn find_port(ports: Vec<PortInfo>, name: &str) -> Option<PortInfo> {
+    let name = base_name(name);
+    ports.into_iter().find(|info| base_name(&info.name) == name)
+}
+
+/// `name` without the `(COMn)` suffix of a Windows friendly name such as
+/// `USB Serial Device (COM7)`
+fn base_name(name: &str) -> &str {
+    let name = name.trim_end();
+    let Some((base, suffix)) = name
+        .strip_suffix(')')
+        .and_then(|name| name.rsplit_once('('))
+    else {
+        return name;
+    };
+
+    match suffix.strip_prefix("COM") {
+        Some(number) if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) => {
+            base.trim_end()
+        }
+        _ => name,
+    }
+}
+
+impl AsyncRead for ReconnectingPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.get_mut()
+            .poll_io(cx, |port, cx| port.poll_read(cx, buf))
+    }
+}
+
+impl AsyncWrite for ReconnectingPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_io(cx, |port, cx| port.poll_write(cx, buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_io(cx, |port, cx| port.poll_flush(cx))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut()
+            .poll_io(cx, |port, cx| port.poll_shutdown(cx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use std::{io::ErrorKind, time::Duration};
+
+    #[cfg(unix)]
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[cfg(unix)]
+    use super::ReconnectingPort;
+    use super::{base_name, find_port};
+    use crate::PortInfo;
+    #[cfg(unix)]
+    use crate::{SerialPort, new};
+
+    #[test]
+    fn test_base_name() {
+        assert_eq!(base_name("USB Serial Device (COM7)"), "USB Serial Device");
+        assert_eq!(base_name("USB Serial Device (COM12) "), "USB Serial Device");
+        assert_eq!(base_name("USB Serial Device"), "USB Serial Device");
+        assert_eq!(base_name("Adapter (rev 2)"), "Adapter (rev 2)");
+        assert_eq!(base_name("Adapter (COM)"), "Adapter (COM)");
+    }
+
+    #[test]
+    fn test_find_renumbered_port() {
+        let port = |path: &str, name: &str| PortInfo {
+            path: path.to_owned(),
+            name: name.to_owned(),
+            ..PortInfo::default()
+        };
+        // The adapter was COM3 when opened and came back as COM4
+        let ports = || {
+            vec![
+                port("COM1", "Communications Port (COM1)"),
+                port("COM4", "Silicon Labs CP210x USB to UART Bridge (COM4)"),
+            ]
+        };
+
+        let found = find_port(ports(), "Silicon Labs CP210x USB to UART Bridge (COM3)");
+        assert_eq!(found.unwrap().path, "COM4");
+        let found = find_port(ports(), "Silicon Labs CP210x USB to UART Bridge");
+        assert_eq!(found.unwrap().path, "COM4");
+        assert!(find_port(ports(), "USB Serial Device (COM3)").is_none());
+    }
+
+    /// A link to the slave of a new pty pair, standing in for a device path
+    /// that leads to another device once it is plugged in again
+    #[cfg(unix)]
+    fn plug_in(link: &std::path::Path) -> crate::TTYPort {
+        let (master, slave) = crate::pty_pair().unwrap();
+        let _ = std::fs::remove_file(link);
+        std::os::unix::fs::symlink(slave.name(), link).unwrap();
+        drop(slave);
+        master
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_reopen_after_failed_write() {
+        let link =
+            std::env::temp_dir().join(format!("serialport-reconnect-{}", std::process::id()));
+        let master = plug_in(&link);
+        let path = link.to_str().unwrap();
+
+        let mut port = ReconnectingPort::open(new(path, 9600))
+            .await
+            .unwrap()
+            .backoff(Duration::from_millis(10));
+
+        // The write fails with EIO once the device is gone, and is retried
+        // on the device plugged in meanwhile
+        drop(master);
+        let mut master = plug_in(&link);
+        tokio::time::timeout(Duration::from_secs(5), port.write_all(b"ping"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(port.is_connected());
+
+        let mut buf = [0; 4];
+        master.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        std::fs::remove_file(&link).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_disconnect_without_waiting() {
+        let (mut master, slave) = crate::pty_pair().unwrap();
+        let path = slave.name();
+        drop(slave);
+
+        let mut port = ReconnectingPort::open(new(&path, 9600))
+            .await
+            .unwrap()
+            .wait_for_reconnect(false);
+
+        master.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        port.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // The pty is gone with its master, so it never comes back
+        drop(master);
+        let err = port.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        assert!(!port.is_connected());
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{Clear, Result, SerialPort};
+
+/// A port shared between tasks
+///
+/// Cloning a `SharedPort` gives another handle to the same port, so one task
+/// can, for example, read from it while another monitors and drives the
+/// control lines.
+///
+/// ## Locking
+///
+/// The port sits behind one mutex, which is only ever held for the duration of
+/// a single call into the port: a control or configuration method, or one
+/// poll of a read or write. It is never held across an `.await`, so a read
+/// waiting for data does not hold up a quick [`SharedPort::set_rts`] issued by
+/// another task.
+///
+/// Like the port itself, reads may be issued from one task and writes from
+/// another at the same time. Reading (or writing) from several tasks at once
+/// is not supported, as only the task that polled last is woken.
+pub struct SharedPort<P> {
+    port: Arc<Mutex<P>>,
+}
+
+impl<P> Clone for SharedPort<P> {
+    fn clone(&self) -> Self {
+        SharedPort {
+            port: self.port.clone(),
+        }
+    }
+}
+
+impl<P: SerialPort> SharedPort<P> {
+    pub fn new(port: P) -> Self {
+        SharedPort {
+            port: Arc::new(Mutex::new(port)),
+        }
+    }
+
+    /// Runs `f` with exclusive access to the port, e.g. to reconfigure it
+    ///
+    /// Reads and writes in other tasks are held up until `f` returns, so it
+    /// should not block.
+    pub fn lock<T>(&self, f: impl FnOnce(&mut P) -> T) -> T {
+        f(&mut self.guard())
+    }
+
+    pub fn set_rts(&self, level: bool) -> Result<()> {
+        self.lock(|port| port.set_rts(level))
+    }
+
+    pub fn set_dtr(&self, level: bool) -> Result<()> {
+        self.lock(|port| port.set_dtr(level))
+    }
+
+    pub fn set_break(&self, level: bool) -> Result<()> {
+        self.lock(|port| port.set_break(level))
+    }
+
+    pub fn cts(&self) -> Result<bool> {
+        self.lock(|port| port.cts())
+    }
+
+    pub fn dsr(&self) -> Result<bool> {
+        self.lock(|port| port.dsr())
+    }
+
+    pub fn ri(&self) -> Result<bool> {
+        self.lock(|port| port.ri())
+    }
+
+    pub fn cd(&self) -> Result<bool> {
+        self.lock(|port| port.cd())
+    }
+
+    pub fn bytes_to_read(&self) -> Result<u32> {
+        self.lock(|port| port.bytes_to_read())
+    }
+
+    pub fn bytes_to_write(&self) -> Result<u32> {
+        self.lock(|port| port.bytes_to_write())
+    }
+
+    pub fn clear(&self, buffer_to_clear: Clear) -> Result<()> {
+        self.lock(|port| port.clear(buffer_to_clear))
+    }
+}
+
+impl<P> SharedPort<P> {
+    fn guard(&self) -> MutexGuard<'_, P> {
+        // A panic while locked cannot leave the port itself inconsistent
+        self.port.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<P: AsyncRead + Unpin> AsyncRead for SharedPort<P> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.guard()).poll_read(cx, buf)
+    }
+}
+
+impl<P: AsyncWrite + Unpin> AsyncWrite for SharedPort<P> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.guard()).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.guard()).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.guard()).poll_shutdown(cx)
+    }
+}
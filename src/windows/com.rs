@@ -1,140 +1,1390 @@
 use std::{
+    fmt,
     io::{self, Error},
     mem::MaybeUninit,
-    os::windows::prelude::{AsRawHandle, RawHandle},
+    os::windows::prelude::{AsRawHandle, FromRawHandle, OwnedHandle, RawHandle},
     pin::Pin,
     ptr::null_mut,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
     task::{Context, Poll},
+    thread,
+    time::Duration,
 };
 
 use tokio::{
-    io::{AsyncRead, AsyncWrite},
-    net::windows::named_pipe::NamedPipeClient,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    sync::{mpsc, watch},
+    time::Instant,
 };
 use windows_sys::Win32::{
     Devices::Communication::{
-        CLRDTR, CLRRTS, ClearCommBreak, ClearCommError, EVENPARITY, EscapeCommFunction,
-        GetCommModemStatus, MS_CTS_ON, MS_DSR_ON, MS_RING_ON, MS_RLSD_ON, NOPARITY, ODDPARITY,
-        ONE5STOPBITS, ONESTOPBIT, PURGE_RXABORT, PURGE_RXCLEAR, PURGE_TXABORT, PURGE_TXCLEAR,
-        PurgeComm, SETDTR, SETRTS, SetCommBreak, TWOSTOPBITS,
+        CE_OVERRUN, CE_RXOVER, CLRDTR, CLRRTS, COMSTAT, ClearCommBreak, ClearCommError, DCB,
+        EV_CTS, EV_DSR, EV_RING, EV_RLSD, EV_RXCHAR, EV_TXEMPTY, EscapeCommFunction, MS_CTS_ON,
+        MS_DSR_ON, MS_RING_ON, MS_RLSD_ON, PurgeComm, SETDTR, SETRTS, SETXOFF, SETXON,
+        SetCommBreak, SetupComm,
+    },
+    Foundation::{
+        ERROR_ACCESS_DENIED, ERROR_IO_PENDING, ERROR_OPERATION_ABORTED, ERROR_SHARING_VIOLATION,
+        GENERIC_READ, GENERIC_WRITE, HANDLE, INVALID_HANDLE_VALUE,
+    },
+    Storage::FileSystem::{
+        CreateFileW, FILE_FLAG_OVERLAPPED, FILE_SHARE_MODE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+        FlushFileBuffers, OPEN_EXISTING, ReadFile, WriteFile,
     },
-    Foundation::{CloseHandle, GENERIC_READ, GENERIC_WRITE, HANDLE, INVALID_HANDLE_VALUE},
-    Storage::FileSystem::{CreateFileW, FILE_FLAG_OVERLAPPED, OPEN_EXISTING},
+    System::IO::OVERLAPPED,
 };
 
 use crate::{
-    Clear, DataBits, FlowControl, Parity, Result, SerialPort, SerialPortBuilder, StopBits,
+    Access, Clear, DataBits, FlowControl, FlowControlState, OverrunPolicy, Parity, PortConfig,
+    PortInfo, Result, RtsLevel, SerialPort, SerialPortBuilder, ShareMode, StopBits,
     windows::dcb::{self, BitOperation},
+    windows::error,
+    windows::events::{self, CommEventMask, CommEvents, ModemStatus},
+    windows::overlapped::Overlapped,
+    windows::properties::{self, PortProperties},
+    windows::purge::PurgeFlags,
+    windows::timeouts::{self, CommTimeouts},
 };
 
+/// One direction of overlapped I/O and the buffer the OS transfers from/into
+struct Transfer {
+    overlapped: Overlapped,
+    buf: Vec<u8>,
+    /// Start of the bytes in `buf` a read has not handed out yet
+    pos: usize,
+    pending: bool,
+    /// The read in flight is being stopped by [`COMPort::cancel_read`], as
+    /// opposed to aborted by a purge
+    cancelled: bool,
+    /// The input was cleared while the read was in flight, so what it
+    /// received is dropped as it completes
+    discard: bool,
+}
+
+impl Transfer {
+    fn new() -> io::Result<Transfer> {
+        Ok(Transfer {
+            overlapped: Overlapped::new()?,
+            buf: Vec::new(),
+            pos: 0,
+            pending: false,
+            cancelled: false,
+            discard: false,
+        })
+    }
+}
+
+/// Drives RTS as the direction line of an RS-485 transceiver, see
+/// [`crate::SerialPortBuilder::rs485_manual`]
+struct Rs485 {
+    transmit_level: RtsLevel,
+    /// Waits for `EV_TXEMPTY`, the only event selected
+    events: CommEvents,
+    /// RTS is at the transmit level
+    transmitting: bool,
+}
+
+impl Rs485 {
+    fn new(handle: OwnedHandle, transmit_level: RtsLevel) -> Result<Rs485> {
+        let mut rs485 = Rs485 {
+            transmit_level,
+            events: CommEvents::new(handle)?,
+            transmitting: true,
+        };
+        events::set_comm_mask(rs485.events.handle(), EV_TXEMPTY)?;
+        rs485.set_transmitting(false)?;
+        Ok(rs485)
+    }
+
+    fn set_transmitting(&mut self, transmitting: bool) -> Result<()> {
+        if self.transmitting == transmitting {
+            return Ok(());
+        }
+
+        let high = transmitting == (self.transmit_level == RtsLevel::High);
+        let function = if high { SETRTS } else { CLRRTS };
+        if unsafe { EscapeCommFunction(self.events.handle(), function) } == 0 {
+            return Err(error::last_os_error());
+        }
+        self.transmitting = transmitting;
+        Ok(())
+    }
+
+    /// Switch to transmit ahead of a write
+    fn start_write(&mut self) -> Result<()> {
+        // Selecting the events again forgets an `EV_TXEMPTY` an earlier write
+        // left behind, so the next flush waits for this write to be sent
+        events::set_comm_mask(self.events.handle(), EV_TXEMPTY)?;
+        self.set_transmitting(true)
+    }
+
+    /// Switch back to receive once the last byte written has been sent
+    fn poll_release(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while self.transmitting {
+            match std::task::ready!(self.events.poll_wait(cx)) {
+                // A wait cut short by selecting the events again
+                Ok(fired) if fired & EV_TXEMPTY == 0 => continue,
+                Ok(_) => return Poll::Ready(self.set_transmitting(false)),
+                Err(e) => {
+                    let _ = self.set_transmitting(false);
+                    return Poll::Ready(Err(e));
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
 pub struct COMPort {
     path: String,
-    pipe: NamedPipeClient,
+    handle: OwnedHandle,
+    access: Access,
+    read: Transfer,
+    write: Transfer,
+    overruns: AtomicU64,
+    /// An overrun was detected that the read path has not acted on yet
+    overrun_pending: AtomicBool,
+    overrun_policy: OverrunPolicy,
+    /// The `PURGE_RX*` flags of the purges since the read path last looked,
+    /// see [`COMPort::take_input_purge`]
+    input_purged: AtomicU32,
+    /// A purge aborted the write in flight, which is then not reported as a
+    /// failure
+    output_aborted: AtomicBool,
+    recover_rx_overflow: bool,
+    /// Input buffer overflows cleared with `recover_rx_overflow` on
+    recovered_overflows: AtomicU64,
+    /// Reads gather bytes over the interval timeout, see
+    /// [`crate::SerialPortBuilder::read_coalesce`]
+    coalesce: bool,
+    /// Writes shorter than this are checked for completion in place, see
+    /// [`COMPort::set_sync_threshold`]
+    sync_threshold: usize,
+    on_overrun: Mutex<Option<Box<dyn FnMut() + Send>>>,
+    verify_config: bool,
+    check_max_baud_rate: bool,
+    flush_on_drop: bool,
+    rs485: Option<Rs485>,
+    /// Dropped with the port, which stops the tasks watching it
+    closed: watch::Sender<()>,
 }
 
 unsafe impl Send for COMPort {}
 
 impl COMPort {
     pub fn open(builder: &SerialPortBuilder) -> Result<COMPort> {
-        let mut name = Vec::<u16>::with_capacity(4 + builder.path.len() + 1);
+        let share_mode: FILE_SHARE_MODE = match builder.share_mode {
+            ShareMode::Exclusive => 0,
+            ShareMode::Read => FILE_SHARE_READ,
+            ShareMode::Write => FILE_SHARE_WRITE,
+            ShareMode::ReadWrite => FILE_SHARE_READ | FILE_SHARE_WRITE,
+        };
 
-        if !builder.path.starts_with('\\') {
-            name.extend(r"\\.\".encode_utf16());
-        }
+        let access = match builder.access {
+            Access::ReadWrite => GENERIC_READ | GENERIC_WRITE,
+            Access::ReadOnly => GENERIC_READ,
+            Access::WriteOnly => GENERIC_WRITE,
+        };
 
-        name.extend(builder.path.encode_utf16());
-        name.push(0);
+        let handle = match builder.open_timeout {
+            None => create_file(&builder.path, access, share_mode)?,
+            Some(timeout) => {
+                // Opening a Bluetooth port blocks until its channel is
+                // connected, so give up on it after the timeout. Should the
+                // open still succeed, the handle is closed as the send fails.
+                let (tx, rx) = std::sync::mpsc::channel();
+                let path = builder.path.clone();
+                thread::spawn(move || {
+                    let _ = tx.send(create_file(&path, access, share_mode));
+                });
+                rx.recv_timeout(timeout)
+                    .map_err(|_| crate::Error::TimedOut)??
+            }
+        };
 
-        let handle = unsafe {
-            CreateFileW(
-                name.as_ptr(),
-                GENERIC_READ | GENERIC_WRITE,
-                0,
-                null_mut(),
-                OPEN_EXISTING,
-                FILE_FLAG_OVERLAPPED,
-                0 as HANDLE,
-            )
+        let mut com = COMPort {
+            path: builder.path.to_owned(),
+            handle,
+            access: builder.access,
+            read: Transfer::new()?,
+            write: Transfer::new()?,
+            overruns: AtomicU64::new(0),
+            overrun_pending: AtomicBool::new(false),
+            overrun_policy: builder.overrun_policy,
+            input_purged: AtomicU32::new(0),
+            output_aborted: AtomicBool::new(false),
+            recover_rx_overflow: builder.recover_rx_overflow,
+            recovered_overflows: AtomicU64::new(0),
+            coalesce: builder.read_coalesce.is_some(),
+            sync_threshold: 0,
+            on_overrun: Mutex::new(None),
+            verify_config: builder.verify_config,
+            check_max_baud_rate: builder.check_max_baud_rate,
+            flush_on_drop: builder.flush_on_drop,
+            rs485: None,
+            closed: watch::Sender::new(()),
         };
 
-        if handle == INVALID_HANDLE_VALUE {
-            return Err(Error::last_os_error().into());
+        if let Some((input, output)) = builder.queue_sizes
+            && unsafe { SetupComm(com.as_raw_handle(), input, output) } == 0
+        {
+            return Err(error::last_os_error());
         }
 
-        let pipe = unsafe {
-            NamedPipeClient::from_raw_handle(handle).inspect_err(|_| {
-                CloseHandle(handle);
-            })
-        }?;
+        // Configure the port based on the builder settings
+        if !builder.preserve_settings {
+            com.check_baud_rate(builder.baudrate)?;
 
-        let com = COMPort {
-            path: builder.path.to_owned(),
-            pipe,
+            let mut dcb = dcb::get_dcb(com.as_raw_handle())?;
+            dcb::default(&mut dcb);
+            dcb::set_baud_rate(&mut dcb, builder.baudrate);
+            dcb::set_data_bits(&mut dcb, builder.data_bits)?;
+            dcb::set_parity(&mut dcb, builder.parity)?;
+            dcb::set_stop_bits(&mut dcb, builder.stop_bits)?;
+            dcb::set_flow_control(&mut dcb, builder.flow_control)?;
+            dcb::set_error_replacement(&mut dcb, builder.error_replacement);
+            if let Some((xon_lim, xoff_lim)) = builder.flow_limits {
+                dcb::set_flow_limits(&mut dcb, xon_lim, xoff_lim);
+            }
+            com.write_dcb(dcb)?;
+        }
+
+        let timeouts = match builder.read_coalesce {
+            Some(gap) => timeouts::coalescing(gap),
+            None => timeouts::DEFAULT,
         };
+        timeouts::set_timeouts(com.as_raw_handle(), &timeouts)?;
 
-        // Configure the port based on the builder settings
-        let mut dcb = dcb::get_dcb(handle)?;
-        dcb::default(&mut dcb);
-        dcb::set_baud_rate(&mut dcb, builder.baudrate);
-        dcb::set_data_bits(&mut dcb, builder.data_bits)?;
-        dcb::set_parity(&mut dcb, builder.parity)?;
-        dcb::set_stop_bits(&mut dcb, builder.stop_bits)?;
-        dcb::set_flow_control(&mut dcb, builder.flow_control)?;
-        dcb::set_dcb(handle, dcb)?;
+        if let Some(level) = builder.rs485_manual {
+            com.rs485 = Some(Rs485::new(com.handle.try_clone()?, level)?);
+        }
+
+        if builder.purge_on_open {
+            com.clear_with(PurgeFlags::RX_CLEAR | PurgeFlags::TX_CLEAR)?;
+        }
 
         Ok(com)
     }
 
-    /// Configurate DTR/RTS
-    fn escape_comm_function(&mut self, function: u32) -> Result<()> {
+    /// Returns the read and write timeouts currently in effect
+    ///
+    /// Unless changed with [`COMPort::set_timeouts`], these are the ones the
+    /// read path is built around, which make a read wait for the first byte.
+    pub fn get_timeouts(&self) -> Result<CommTimeouts> {
+        timeouts::get_timeouts(self.as_raw_handle()).map(CommTimeouts::from)
+    }
+
+    /// Sets the read and write timeouts of the port
+    ///
+    /// Fails with [`crate::Error::InvalidArgument`] for timeouts that make
+    /// reads return at once when nothing is buffered, see [`CommTimeouts`].
+    pub fn set_timeouts(&mut self, timeouts: CommTimeouts) -> Result<()> {
+        if timeouts.is_non_blocking() {
+            return Err(crate::Error::InvalidArgument(
+                "reads that return at once are not supported".to_owned(),
+            ));
+        }
+        timeouts::set_timeouts(self.as_raw_handle(), &timeouts.into())
+    }
+
+    /// Applies `timeouts` while `f` runs, then restores the previous timeouts
+    ///
+    /// The previous timeouts are restored even if `f` fails or the returned
+    /// future is dropped before it completes.
+    pub async fn with_timeouts<F, T>(&mut self, timeouts: CommTimeouts, f: F) -> Result<T>
+    where
+        F: AsyncFnOnce(&mut Self) -> Result<T>,
+    {
+        let guard = timeouts::RestoreGuard::new(self.as_raw_handle())?;
+        self.set_timeouts(timeouts)?;
+
+        let result = f(self).await;
+        let restored = guard.restore();
+        let value = result?;
+        restored?;
+        Ok(value)
+    }
+
+    /// Runs `f` until `cancel` completes, failing then with
+    /// [`crate::Error::Cancelled`]
+    ///
+    /// `cancel` can be any future, e.g. `token.cancelled()` of a
+    /// `tokio_util` `CancellationToken`; one that is already complete wins
+    /// over `f`. On cancellation, the read and write `f` left in flight are
+    /// cancelled as well, see [`COMPort::cancel_read`], so no I/O is left
+    /// dangling. Bytes received up to then are kept for the next read.
+    pub async fn with_cancel<F, T>(&mut self, cancel: impl Future<Output = ()>, f: F) -> Result<T>
+    where
+        F: AsyncFnOnce(&mut Self) -> Result<T>,
+    {
+        {
+            let operation = f(self);
+            tokio::select! {
+                biased;
+                _ = cancel => {}
+                result = operation => return result,
+            }
+        }
+
+        self.cancel_read();
+        self.cancel_write();
+        Err(crate::Error::Cancelled)
+    }
+
+    /// Checks whether writes shorter than `threshold` bytes completed right
+    /// as they are issued, 0 by default so no write is checked
+    ///
+    /// A write is collected by the next write or flush, which for one still
+    /// in flight means a thread pool wait; that adds latency to chatty
+    /// protocols exchanging a few bytes at a time. A write below the threshold
+    /// that the driver completes at once, as it often does for a few bytes
+    /// with room in its queue, is collected in place instead, so the flush
+    /// after it returns at once. One that is still in flight, e.g. as flow
+    /// control holds output off, is left to the next write or flush as usual;
+    /// the calling thread never blocks on it.
+    ///
+    /// Reads need no such setting: bytes the driver already holds are read in
+    /// place, and waiting for bytes that have not arrived yet must not block.
+    pub fn set_sync_threshold(&mut self, threshold: usize) {
+        self.sync_threshold = threshold;
+    }
+
+    /// Returns how many receive overruns were detected since the port was opened
+    ///
+    /// An overrun means received data was lost, either because the driver's
+    /// input buffer was full (`CE_RXOVER`) or the UART's FIFO was (`CE_OVERRUN`).
+    pub fn overrun_count(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many overflows of the driver's input buffer were cleared
+    /// while [`crate::SerialPortBuilder::recover_rx_overflow`] is on
+    ///
+    /// Received data was lost each time. The overflows are counted by
+    /// [`COMPort::overrun_count`] as well.
+    pub fn recovered_overflows(&self) -> u64 {
+        self.recovered_overflows.load(Ordering::Relaxed)
+    }
+
+    /// Registers a callback invoked each time an overrun is detected
+    pub fn on_overrun<F: FnMut() + Send + 'static>(&mut self, callback: F) {
+        if let Ok(mut on_overrun) = self.on_overrun.lock() {
+            *on_overrun = Some(Box::new(callback));
+        }
+    }
+
+    /// Read the queue status and record any overrun reported alongside it
+    fn comm_status(&self) -> Result<COMSTAT> {
+        self.clear_comm_error().map(|(_, comstat)| comstat)
+    }
+
+    /// Clear the line errors, returning them with the queue status, and
+    /// record any overrun among them
+    fn clear_comm_error(&self) -> Result<(u32, COMSTAT)> {
+        let mut errors: u32 = 0;
+        let mut comstat = MaybeUninit::uninit();
+
+        if unsafe { ClearCommError(self.as_raw_handle(), &mut errors, comstat.as_mut_ptr()) == 0 } {
+            return Err(error::last_os_error());
+        }
+
+        if errors & (CE_RXOVER | CE_OVERRUN) != 0 {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+            self.overrun_pending.store(true, Ordering::Relaxed);
+            if let Ok(mut on_overrun) = self.on_overrun.lock()
+                && let Some(callback) = on_overrun.as_mut()
+            {
+                callback();
+            }
+        }
+        if errors & CE_RXOVER != 0 && self.recover_rx_overflow {
+            self.recovered_overflows.fetch_add(1, Ordering::Relaxed);
+        }
+
+        Ok((errors, unsafe { comstat.assume_init() }))
+    }
+
+    /// Act on an overrun detected since the last call as the policy says,
+    /// returning whether received data was discarded
+    fn apply_overrun_policy(&mut self) -> Result<bool> {
+        let overrun = self.overrun_pending.swap(false, Ordering::Relaxed);
+        let discard = overrun_action(self.overrun_policy, overrun)?;
+        if discard {
+            self.clear(Clear::InputSoft)?;
+            self.read.buf.clear();
+            self.read.pos = 0;
+        }
+        Ok(discard)
+    }
+
+    /// Drop the bytes held back for the next read if the input was cleared
+    /// since the last call, and if the read in flight was aborted as well,
+    /// what it received
+    fn take_input_purge(&mut self) {
+        let purged = self.input_purged.swap(0, Ordering::Relaxed);
+        let read = &mut self.read;
+        if purged != 0 {
+            read.buf.clear();
+            read.pos = 0;
+        }
+        if purged & PurgeFlags::RX_ABORT.bits() != 0 && read.pending {
+            read.discard = true;
+        }
+    }
+
+    /// Wait for the in-flight write, if any, to complete
+    fn poll_write_complete(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.write.pending {
+            self.output_aborted.store(false, Ordering::Relaxed);
+            return Poll::Ready(Ok(()));
+        }
+
+        let handle = self.as_raw_handle();
+        let result = std::task::ready!(self.write.overlapped.poll(handle, cx));
+        self.write.pending = false;
+
+        // Clearing the output dropped the write on purpose, and nothing of it
+        // is left to be sent
+        if self.output_aborted.swap(false, Ordering::Relaxed) {
+            if let Some(rs485) = &mut self.rs485 {
+                rs485.set_transmitting(false)?;
+            }
+            if let Err(e) = &result
+                && is_aborted(e)
+            {
+                return Poll::Ready(Ok(()));
+            }
+        }
+        Poll::Ready(result.map(|_| ()).map_err(|e| error::from_io(e).into()))
+    }
+
+    /// Waits until `ready` holds, checking it again each time one of the
+    /// events in `mask` fires rather than polling
+    ///
+    /// The events are selected before the first check, so one that fires in
+    /// between is recorded rather than missed.
+    async fn wait_for_events(
+        &self,
+        mask: u32,
+        mut ready: impl FnMut(&Self) -> Result<bool>,
+    ) -> Result<()> {
+        let mut events = CommEvents::new(self.handle.try_clone()?)?;
+        events::set_comm_mask(events.handle(), mask)?;
+        while !ready(self)? {
+            // A wait cut short by another waiter selecting events of its own
+            if events.wait().await? & mask == 0 {
+                events::set_comm_mask(events.handle(), mask)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits until the driver's output queue is empty
+    ///
+    /// The queue is checked each time the driver signals `EV_TXEMPTY`. The
+    /// events a port signals are selected for all handles to it at once, so
+    /// this replaces the events any other waiter watches for, such as
+    /// [`COMPort::monitor_modem`]. This can wait forever if the peer holds off
+    /// transmission through flow control, see [`COMPort::drain_timeout`].
+    pub async fn drain(&self) -> Result<()> {
+        self.wait_for_events(EV_TXEMPTY, |port| Ok(port.bytes_to_write()? == 0))
+            .await
+    }
+
+    /// Waits until the driver's output queue is empty, giving up with
+    /// [`crate::Error::TimedOut`] after `timeout`
+    pub async fn drain_timeout(&self, timeout: Duration) -> Result<()> {
+        tokio::time::timeout(timeout, self.drain())
+            .await
+            .map_err(|_| crate::Error::TimedOut)?
+    }
+
+    /// Waits until the last byte written has left the UART, e.g. before an
+    /// RS-485 transceiver is switched back to receive
+    ///
+    /// Output passes two stages: the driver's output queue, which it hands to
+    /// the UART's FIFO, and the FIFO with the shift register behind it, which
+    /// hold several characters at once and are only done once the last bit is
+    /// out. Drivers signal `EV_TXEMPTY` once both queue and FIFO have run
+    /// empty; this waits for that, then one more character time for the shift
+    /// register.
+    ///
+    /// The event can only be waited for while output is in flight, i.e. a
+    /// write is still being handed to the driver or bytes are queued. If
+    /// nothing is, e.g. as nothing was written or the last write was handed
+    /// over completely already, this only waits the one character time, and
+    /// the last few bytes may still be in the FIFO. So call this right after
+    /// writing rather than after [`AsyncWriteExt::flush`].
+    ///
+    /// Like [`COMPort::drain`], this waits for good if the peer holds off
+    /// transmission through flow control, and replaces the events other
+    /// waiters watch for. Should another waiter change them in turn, this
+    /// fails with [`io::ErrorKind::Interrupted`].
+    pub async fn drain_complete(&self) -> Result<()> {
+        let mut events = CommEvents::new(self.handle.try_clone()?)?;
+        // Selected before looking at the output, so the event it raises
+        // from now on is recorded even while no wait is in flight
+        events::set_comm_mask(events.handle(), EV_TXEMPTY)?;
+
+        let writing = self.write.pending && !self.write.overlapped.is_complete();
+        if writing || self.bytes_to_write()? > 0 {
+            wait_tx_empty(&mut events).await?;
+        }
+        tokio::time::sleep(self.char_time()?).await;
+        Ok(())
+    }
+
+    /// Waits until the driver's output queue has room for at least
+    /// `min_free` more bytes
+    ///
+    /// Lets a sender pace itself rather than queue up writes the driver
+    /// cannot take yet. A driver that does not report the size of its output
+    /// queue, see [`COMPort::queue_capacity`], is taken to have room once the
+    /// queue is empty. Fails with [`crate::Error::InvalidArgument`] if the
+    /// queue could never hold `min_free` bytes.
+    ///
+    /// Drivers only signal `EV_TXEMPTY` once the queue has run empty, so
+    /// without room at the time of the call this waits for that, like
+    /// [`COMPort::drain`] does, and replaces the events other waiters watch
+    /// for as well. It can wait forever if the peer holds off transmission
+    /// through flow control, see [`COMPort::wait_write_space_timeout`].
+    pub async fn wait_write_space(&self, min_free: u32) -> Result<()> {
+        let (_, capacity) = self.queue_capacity()?;
+        let max_queued = match capacity {
+            0 => 0,
+            capacity if min_free > capacity => {
+                return Err(crate::Error::InvalidArgument(format!(
+                    "{min_free} bytes exceed the output queue of {capacity}"
+                )));
+            }
+            capacity => capacity - min_free,
+        };
+
+        self.wait_for_events(EV_TXEMPTY, |port| Ok(port.bytes_to_write()? <= max_queued))
+            .await
+    }
+
+    /// Waits until the driver's output queue has room for at least
+    /// `min_free` more bytes, giving up with [`crate::Error::TimedOut`] after
+    /// `timeout`
+    pub async fn wait_write_space_timeout(&self, min_free: u32, timeout: Duration) -> Result<()> {
+        tokio::time::timeout(timeout, self.wait_write_space(min_free))
+            .await
+            .map_err(|_| crate::Error::TimedOut)?
+    }
+
+    /// Waits for the in-flight write to complete, giving up with
+    /// [`crate::Error::TimedOut`] after `timeout`
+    ///
+    /// On timeout the write is cancelled, so no operation is left in flight;
+    /// whatever part of it the driver had not yet sent is discarded.
+    pub async fn flush_timeout(&mut self, timeout: Duration) -> Result<()> {
+        match tokio::time::timeout(timeout, self.flush()).await {
+            Ok(result) => Ok(result?),
+            Err(_) => {
+                self.cancel_write();
+                Err(crate::Error::TimedOut)
+            }
+        }
+    }
+
+    /// Whether a read is in flight, e.g. one left behind by a dropped read
+    /// future, see [`COMPort::cancel_read`]
+    ///
+    /// The read counts as in flight from when it is issued until a later
+    /// read collects its result or it is cancelled, so this stays true for a
+    /// read the OS has completed but the port has not looked at yet.
+    /// Clearing the input buffer with [`SerialPort::clear`] cuts such a read
+    /// short and drops what it received.
+    pub fn has_pending_read(&self) -> bool {
+        self.read.pending
+    }
+
+    /// Whether a write is in flight, from when it is issued until a later
+    /// write or a flush collects its result
+    ///
+    /// Writes return once they are handed to the OS, so this is true after
+    /// most writes until the port is flushed; see
+    /// [`crate::SerialPortBuilder::flush_on_drop`] for what becomes of such
+    /// a write when the port is dropped. Clearing the output buffer with
+    /// [`SerialPort::clear`] aborts it.
+    pub fn has_pending_write(&self) -> bool {
+        self.write.pending
+    }
+
+    /// Stops a read the OS still has in flight and returns how many received
+    /// bytes the port holds back for the next read
+    ///
+    /// Dropping a read future, e.g. one that lost a `select!`, leaves its read
+    /// in flight, so nothing that arrives is lost: the next read picks it up.
+    /// This stops it early instead, e.g. before handing the port to code that
+    /// reads the raw handle; whatever it received up to then is kept all the
+    /// same.
+    pub fn cancel_read(&mut self) -> usize {
+        self.take_input_purge();
+        if self.read.pending {
+            // Marked first, so the abort is told apart from one by a purge
+            self.read.cancelled = true;
+            let result = self.read.overlapped.cancel(self.as_raw_handle());
+            let n = self.finish_read(result).unwrap_or(0);
+            unsafe { self.read.buf.set_len(n) };
+            self.read.pos = 0;
+        }
+        self.read.buf.len() - self.read.pos
+    }
+
+    /// Collect the result of the read that was in flight, returning how many
+    /// of the bytes it received to keep
+    fn finish_read(&mut self, result: io::Result<u32>) -> Result<usize> {
+        let read = &mut self.read;
+        read.pending = false;
+        let cancelled = std::mem::take(&mut read.cancelled);
+        if std::mem::take(&mut read.discard) {
+            return Ok(0);
+        }
+
+        match result {
+            // A read stopped by `cancel_read` keeps the bytes it did receive
+            Err(e) if is_aborted(&e) && cancelled => Ok(read.overlapped.transferred() as usize),
+            Err(e) if is_aborted(&e) => {
+                let (errors, _) = self.clear_comm_error()?;
+                match aborted_read(errors, self.recover_rx_overflow) {
+                    AbortedRead::Drop => Ok(0),
+                    AbortedRead::Keep => Ok(self.read.overlapped.transferred() as usize),
+                    AbortedRead::Fail => Err(error::from_io(e)),
+                }
+            }
+            result => Ok(result.map_err(error::from_io)? as usize),
+        }
+    }
+
+    fn cancel_write(&mut self) {
+        if self.write.pending {
+            let _ = self.write.overlapped.cancel(self.as_raw_handle());
+            self.write.pending = false;
+        }
+        if let Some(rs485) = &mut self.rs485 {
+            let _ = rs485.set_transmitting(false);
+        }
+    }
+
+    /// Reads bytes into `buf` without consuming them; the next read returns
+    /// the same bytes again
+    ///
+    /// Waits for at least one byte like a read does. Windows has no peek on
+    /// serial ports, so the bytes are read and held back by the port; only
+    /// reads through this [`COMPort`] see them, not reads on the raw handle.
+    pub async fn peek(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.take_input_purge();
+        if self.read.pos == self.read.buf.len() && !buf.is_empty() {
+            let mut held = vec![0; buf.len()];
+            let n = self.read(&mut held).await?;
+            held.truncate(n);
+
+            // A read that completed with more than fit is still held back
+            let read = &mut self.read;
+            held.extend_from_slice(&read.buf[read.pos..]);
+            read.buf = held;
+            read.pos = 0;
+        }
+
+        let held = &self.read.buf[self.read.pos..];
+        let n = buf.len().min(held.len());
+        buf[..n].copy_from_slice(&held[..n]);
+        Ok(n)
+    }
+
+    /// Reads exactly enough bytes to fill `buf`, failing with
+    /// [`crate::Error::Incomplete`] if they do not all arrive within `timeout`
+    ///
+    /// The bytes are gathered over as many reads as it takes. On timeout, the
+    /// bytes that did arrive are at the front of `buf`.
+    pub async fn read_exact_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+
+        let mut filled = 0;
+        while filled < buf.len() {
+            match tokio::time::timeout_at(deadline, self.read(&mut buf[filled..])).await {
+                Ok(n) => filled += n?,
+                Err(_) => return Err(crate::Error::Incomplete(filled)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits until `len` bytes have been received, then reads exactly those,
+    /// failing with [`crate::Error::TimedOut`] if they do not all arrive
+    /// within `timeout`
+    ///
+    /// Bytes received beyond `len` are left for the next read. On timeout
+    /// nothing is consumed, so the next read still returns the partial frame.
+    /// A read left in flight by a dropped read future is cancelled first, see
+    /// [`COMPort::cancel_read`].
+    ///
+    /// The input is checked each time the driver signals `EV_RXCHAR`. Like
+    /// [`COMPort::drain`], this replaces the events other waiters watch for.
+    pub async fn read_frame(&mut self, len: usize, timeout: Duration) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        self.cancel_read();
+
+        let arrived =
+            self.wait_for_events(EV_RXCHAR, |port| Ok(port.bytes_to_read()? as usize >= len));
+        match tokio::time::timeout_at(deadline, arrived).await {
+            Ok(result) => result?,
+            Err(_) => return Err(crate::Error::TimedOut),
+        }
+
+        let mut frame = vec![0; len];
+        self.read_exact(&mut frame).await?;
+        Ok(frame)
+    }
+
+    /// Writes `data` as 9-bit frames, for multidrop buses that mark address
+    /// bytes with the 9th bit
+    ///
+    /// The 9th bit is sent as the parity bit: [`Parity::Mark`] for the bytes
+    /// whose `address_mask` entry is `true`, [`Parity::Space`] for the others.
+    /// The configuration the port had before is restored afterwards, also if
+    /// this fails or the future is dropped.
+    ///
+    /// Every switch between address and data bytes waits for the bytes before
+    /// it to leave the UART, see [`COMPort::drain_complete`], and reconfigures
+    /// the port, which costs at least a few milliseconds, so this suits
+    /// protocols with few address bytes.
+    pub async fn write_9bit(&mut self, data: &[u8], address_mask: &[bool]) -> Result<()> {
+        if data.len() != address_mask.len() {
+            return Err(crate::Error::InvalidArgument(
+                "address_mask must be as long as data".to_owned(),
+            ));
+        }
+
+        // Output written before must not go out with the 9th bit set
+        self.flush().await?;
+        self.drain_complete().await?;
+        let saved = dcb::RestoreGuard::new(self.as_raw_handle())?;
+
+        let mut start = 0;
+        while start < data.len() {
+            let address = address_mask[start];
+            let end = address_mask[start..]
+                .iter()
+                .position(|&x| x != address)
+                .map_or(data.len(), |len| start + len);
+
+            self.set_parity(if address { Parity::Mark } else { Parity::Space })?;
+            // Bytes not yet sent would go out with the next parity as well
+            self.write_sent(&data[start..end]).await?;
+            start = end;
+        }
+
+        saved.restore()
+    }
+
+    /// Writes `buf` and waits until its last byte has left the UART
+    ///
+    /// `EV_TXEMPTY` is selected before writing, as for
+    /// [`crate::SerialPortBuilder::rs485_manual`], so it is recorded even if
+    /// the write is handed to the UART at once.
+    async fn write_sent(&mut self, buf: &[u8]) -> Result<()> {
+        let mut events = CommEvents::new(self.handle.try_clone()?)?;
+        events::set_comm_mask(events.handle(), EV_TXEMPTY)?;
+        self.write_all(buf).await?;
+        self.flush().await?;
+        wait_tx_empty(&mut events).await?;
+        tokio::time::sleep(self.char_time()?).await;
+        Ok(())
+    }
+
+    /// Returns what the driver reports about the port, e.g. whether it drives
+    /// an RS-232 or RS-422 line
+    pub fn properties(&self) -> Result<PortProperties> {
+        Ok((&properties::get_properties(self.as_raw_handle())?).into())
+    }
+
+    /// Returns the highest baud rate the driver reports to support, or `None`
+    /// if it has no fixed maximum and takes any rate the hardware can do
+    pub fn max_baud_rate(&self) -> Result<Option<u32>> {
+        let prop = properties::get_properties(self.as_raw_handle())?;
+        Ok(properties::max_baud_rate(prop.dwMaxBaud))
+    }
+
+    /// Fails if checking is on and `baud_rate` is above the driver's maximum,
+    /// see [`crate::SerialPortBuilder::check_max_baud_rate`]
+    fn check_baud_rate(&self, baud_rate: u32) -> Result<()> {
+        if !self.check_max_baud_rate {
+            return Ok(());
+        }
+
+        match self.max_baud_rate()? {
+            Some(max) if baud_rate > max => Err(crate::Error::InvalidArgument(format!(
+                "baud rate {baud_rate} is above the driver's maximum of {max}"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the sizes of the driver's input and output queues, in bytes
+    ///
+    /// These are the sizes actually in use, which may differ from the ones
+    /// requested with [`crate::SerialPortBuilder::queue_sizes`]. A driver
+    /// that does not report them gives 0.
+    pub fn queue_capacity(&self) -> Result<(u32, u32)> {
+        let prop = properties::get_properties(self.as_raw_handle())?;
+        Ok((prop.dwCurrentRxQueue, prop.dwCurrentTxQueue))
+    }
+
+    /// Looks the port up among [`crate::available_ports`], e.g. to log the
+    /// friendly name of the device it was opened by
+    ///
+    /// A port that is not listed, e.g. one opened by a device path rather
+    /// than its COM name, gets a [`PortInfo`] with only the path filled in.
+    pub fn info(&self) -> Result<PortInfo> {
+        let path = self.path.strip_prefix(r"\\.\").unwrap_or(&self.path);
+        let found = crate::available_ports()?
+            .into_iter()
+            .find(|info| info.path.eq_ignore_ascii_case(path));
+
+        Ok(found.unwrap_or_else(|| PortInfo {
+            path: self.path.clone(),
+            ..PortInfo::default()
+        }))
+    }
+
+    /// Returns the name of the driver service backing the port, e.g.
+    /// `usbser`, `FTSER2K` or `com0com`, for telling driver quirks apart in
+    /// bug reports
+    ///
+    /// The port is looked up by its COM name, so one opened by a device path
+    /// has no provider to report.
+    pub fn provider(&self) -> Result<String> {
+        let path = self.path.strip_prefix(r"\\.\").unwrap_or(&self.path);
+        crate::windows::enumerate::driver_service(path)
+    }
+
+    /// Watches the modem status lines, sending a snapshot of them through the
+    /// returned channel whenever CTS, DSR, carrier detect or the ring
+    /// indicator change
+    ///
+    /// The first snapshot is the status at the time of the call. The lines
+    /// are watched by a task spawned on the current tokio runtime, which
+    /// stops once the receiver is dropped, the port is closed or the device
+    /// is gone. Outside a runtime this fails with an [`io::Error`].
+    ///
+    /// The events a port signals are selected for all handles to it at once,
+    /// so this replaces the events any other waiter watches for; should
+    /// another waiter change them in turn, the monitor stops as well.
+    pub fn monitor_modem(&self) -> Result<mpsc::Receiver<ModemStatus>> {
+        let runtime = tokio::runtime::Handle::try_current().map_err(io::Error::other)?;
+        let mut events = CommEvents::new(self.handle.try_clone()?)?;
+        events::set_comm_mask(events.handle(), EV_CTS | EV_DSR | EV_RLSD | EV_RING)?;
+
+        let (tx, rx) = mpsc::channel(16);
+        let initial = ModemStatus::from_raw(self.modem_status()?);
+        let _ = tx.try_send(initial);
+
+        let mut closed = self.closed.subscribe();
+        runtime.spawn(async move {
+            loop {
+                let fired = tokio::select! {
+                    _ = tx.closed() => break,
+                    _ = closed.changed() => break,
+                    fired = events.wait() => fired,
+                };
+
+                let status = match fired {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => match events::modem_status(events.handle()) {
+                        Ok(status) => ModemStatus::from_raw(status),
+                        Err(_) => break,
+                    },
+                };
+                if tx.send(status).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    /// Waits for any of the events in `mask` and returns those of them that
+    /// fired
+    ///
+    /// One wait covers several events, e.g. `CommEventMask::RX_CHAR |
+    /// CommEventMask::CTS | CommEventMask::BREAK`, so a task that cares about
+    /// several of them needs no waiter per event. Events that fired before the
+    /// call are not reported.
+    ///
+    /// The events a port signals are selected for all handles to it at once,
+    /// so this replaces the events any other waiter watches for, such as
+    /// [`COMPort::monitor_modem`] or
+    /// [`crate::SerialPortBuilder::rs485_manual`]. Should another waiter
+    /// change them in turn, this fails with [`io::ErrorKind::Interrupted`].
+    pub async fn wait_events(&self, mask: CommEventMask) -> Result<CommEventMask> {
+        if mask.is_empty() {
+            return Err(crate::Error::InvalidArgument(
+                "no events to wait for".to_owned(),
+            ));
+        }
+
+        let mut events = CommEvents::new(self.handle.try_clone()?)?;
+        events::set_comm_mask(events.handle(), mask.bits())?;
+
+        match events.wait().await? & mask.bits() {
+            0 => Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "the events waited for were changed by another waiter",
+            )
+            .into()),
+            fired => Ok(CommEventMask::from_bits_retain(fired)),
+        }
+    }
+
+    /// Returns whether output is held off because hardware flow control is on
+    /// and the peer has lowered CTS
+    ///
+    /// Helps to tell why writes do not complete. Always `false` if output
+    /// does not follow CTS.
+    pub fn is_cts_blocking(&self) -> Result<bool> {
+        let dcb = dcb::get_dcb(self.as_raw_handle())?;
+        if !dcb.fOutxCtsFlow() {
+            return Ok(false);
+        }
+        Ok(self.modem_status()? & MS_CTS_ON == 0)
+    }
+
+    /// Returns the number of data bits as the driver reports it
+    ///
+    /// Unlike [`SerialPort::data_bits`], this also tells the sizes outside
+    /// 5 to 8 that some industrial drivers report, and which read back as
+    /// [`DataBits::Unknown`] there.
+    pub fn raw_data_bits(&self) -> Result<u8> {
+        dcb::get_dcb(self.as_raw_handle()).map(|dcb| dcb.ByteSize)
+    }
+
+    /// Returns the byte received in place of bytes that fail the parity
+    /// check, see [`crate::SerialPortBuilder::error_replacement`]
+    pub fn error_replacement(&self) -> Result<Option<u8>> {
+        dcb::get_dcb(self.as_raw_handle()).map(|dcb| dcb::error_replacement(&dcb))
+    }
+
+    /// Returns the flow control watermarks of the input queue, `(xon_lim,
+    /// xoff_lim)`, see [`crate::SerialPortBuilder::flow_limits`]
+    pub fn flow_limits(&self) -> Result<(u16, u16)> {
+        dcb::get_dcb(self.as_raw_handle()).map(|dcb| dcb::flow_limits(&dcb))
+    }
+
+    /// Sets the flow control watermarks of the input queue, see
+    /// [`crate::SerialPortBuilder::flow_limits`]
+    pub fn set_flow_limits(&mut self, xon_lim: u16, xoff_lim: u16) -> Result<()> {
+        let mut dcb = dcb::get_dcb(self.as_raw_handle())?;
+        dcb::set_flow_limits(&mut dcb, xon_lim, xoff_lim);
+        self.write_dcb(dcb)
+    }
+
+    /// Apply `dcb`, verifying it was applied as is if configured to
+    fn write_dcb(&self, dcb: DCB) -> Result<()> {
+        dcb::set_dcb(self.as_raw_handle(), dcb)?;
+        if self.verify_config {
+            dcb::verify_dcb(self.as_raw_handle(), &dcb)?;
+        }
+        Ok(())
+    }
+
+    /// Discards the driver's queues and aborts the I/O in flight as `flags`
+    /// say, for finer control than [`SerialPort::clear`]
+    ///
+    /// See [`PurgeFlags`] for which combinations are safe while reads or
+    /// writes are in flight. Clearing the input also drops the bytes the port
+    /// holds back for the next read, e.g. those of a [`COMPort::peek`].
+    pub fn clear_with(&self, flags: PurgeFlags) -> Result<()> {
+        // Recorded first, so a read completing as it is aborted is dropped
+        let input = flags.bits() & (PurgeFlags::RX_ABORT | PurgeFlags::RX_CLEAR).bits();
+        self.input_purged.fetch_or(input, Ordering::Relaxed);
+        if flags.contains(PurgeFlags::TX_ABORT) {
+            self.output_aborted.store(true, Ordering::Relaxed);
+        }
+        match unsafe { PurgeComm(self.as_raw_handle(), flags.bits()) } {
+            0 => Err(error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Issues an extended function with `EscapeCommFunction`
+    ///
+    /// Valid function codes are:
+    ///
+    /// * `SETXOFF` (1) - act as if XOFF was received
+    /// * `SETXON` (2) - act as if XON was received
+    /// * `SETRTS` (3) / `CLRRTS` (4) - raise/lower RTS
+    /// * `SETDTR` (5) / `CLRDTR` (6) - raise/lower DTR
+    /// * `SETBREAK` (8) / `CLRBREAK` (9) - start/end a break
+    ///
+    /// The typed methods such as [`SerialPort::set_rts`] should be preferred.
+    /// Changing a line that the configured flow control also drives, e.g. RTS
+    /// under hardware flow control, can confuse the port.
+    pub fn escape_comm(&mut self, function: u32) -> Result<()> {
         match unsafe { EscapeCommFunction(self.as_raw_handle(), function) } {
-            0 => Err(Error::last_os_error().into()),
+            0 => Err(error::last_os_error()),
             _ => Ok(()),
         }
     }
 
     /// Read CTS/DSR & RI/CD
     fn read_pin(&mut self, pin: u32) -> Result<bool> {
-        let mut status: u32 = 0;
+        self.modem_status().map(|status| status & pin != 0)
+    }
+
+    fn modem_status(&self) -> Result<u32> {
+        events::modem_status(self.as_raw_handle())
+    }
+}
+
+/// The NUL-terminated name to open `path` by, with the `\\.\` device
+/// namespace prefix that COM ports above 9 need
+fn device_name(path: &str) -> Vec<u16> {
+    let mut name = Vec::<u16>::with_capacity(4 + path.len() + 1);
 
-        match unsafe { GetCommModemStatus(self.as_raw_handle(), &mut status) } {
-            0 => Err(Error::last_os_error().into()),
-            _ => Ok(status & pin != 0),
+    if !path.starts_with('\\') {
+        name.extend(r"\\.\".encode_utf16());
+    }
+
+    name.extend(path.encode_utf16());
+    name.push(0);
+    name
+}
+
+/// Whether the port at `path` can be opened right now, see
+/// [`crate::available_ports_openable`]
+pub(crate) fn is_openable(path: &str) -> bool {
+    create_file(path, GENERIC_READ | GENERIC_WRITE, 0).is_ok()
+}
+
+/// Whether the port at `path` is present, so being denied access to it means
+/// another handle holds it open: serial drivers refuse a second opener that
+/// way
+fn is_present(path: &str) -> bool {
+    let path = path.strip_prefix(r"\\.\").unwrap_or(path);
+    crate::available_ports().is_ok_and(|ports| {
+        ports
+            .iter()
+            .any(|info| info.path.eq_ignore_ascii_case(path))
+    })
+}
+
+fn create_file(path: &str, access: u32, share_mode: FILE_SHARE_MODE) -> Result<OwnedHandle> {
+    let name = device_name(path);
+    let handle = unsafe {
+        CreateFileW(
+            name.as_ptr(),
+            access,
+            share_mode,
+            null_mut(),
+            OPEN_EXISTING,
+            FILE_FLAG_OVERLAPPED,
+            0 as HANDLE,
+        )
+    };
+
+    if handle == INVALID_HANDLE_VALUE {
+        let err = Error::last_os_error();
+        return Err(match err.raw_os_error().map(|code| code as u32) {
+            Some(ERROR_SHARING_VIOLATION) => crate::Error::Busy(Some(err)),
+            Some(ERROR_ACCESS_DENIED) if is_present(path) => crate::Error::Busy(Some(err)),
+            _ => err.into(),
+        });
+    }
+
+    Ok(unsafe { OwnedHandle::from_raw_handle(handle) })
+}
+
+/// Issue an overlapped read, which either completes at once or stays in flight
+fn read_file(handle: HANDLE, buf: *mut u8, len: u32, overlapped: *mut OVERLAPPED) -> Result<()> {
+    match unsafe { ReadFile(handle, buf, len, null_mut(), overlapped) } {
+        0 => pending_or_error(),
+        _ => Ok(()),
+    }
+}
+
+/// Issue an overlapped write, which either completes at once or stays in flight
+fn write_file(handle: HANDLE, buf: *const u8, len: u32, overlapped: *mut OVERLAPPED) -> Result<()> {
+    match unsafe { WriteFile(handle, buf, len, null_mut(), overlapped) } {
+        0 => pending_or_error(),
+        _ => Ok(()),
+    }
+}
+
+/// Wait for `EV_TXEMPTY`, which must be the event selected for `events`
+async fn wait_tx_empty(events: &mut CommEvents) -> Result<()> {
+    match events.wait().await? & EV_TXEMPTY {
+        0 => Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "the events waited for were changed by another waiter",
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// The device is taken to be gone when a status query fails right after a
+/// read came back empty, whatever code the driver fails it with
+fn gone(err: crate::Error) -> crate::Error {
+    match err {
+        crate::Error::Io(err) => crate::Error::Disconnected(Some(err)),
+        err => err,
+    }
+}
+
+/// What a read does about an overrun, if there was one: whether it discards
+/// the data received so far, or fails
+fn overrun_action(policy: OverrunPolicy, overrun: bool) -> Result<bool> {
+    if !overrun {
+        return Ok(false);
+    }
+
+    match policy {
+        OverrunPolicy::Ignore => Ok(false),
+        OverrunPolicy::Error => Err(crate::Error::Overrun),
+        OverrunPolicy::Clear => Ok(true),
+    }
+}
+
+/// What a read aborted other than by [`COMPort::cancel_read`] does with the
+/// bytes it received, going by the line errors cleared right after it
+#[derive(Debug, PartialEq, Eq)]
+enum AbortedRead {
+    /// No line error, so a purge aborted it; the bytes go with the queue
+    Drop,
+    /// `fAbortOnError` aborted it on an input overflow, which clearing the
+    /// error recovers from, see [`crate::SerialPortBuilder::recover_rx_overflow`]
+    Keep,
+    /// `fAbortOnError` aborted it on any other line error
+    Fail,
+}
+
+fn aborted_read(errors: u32, recover_rx_overflow: bool) -> AbortedRead {
+    match errors {
+        0 => AbortedRead::Drop,
+        errors if recover_rx_overflow && errors & CE_RXOVER != 0 => AbortedRead::Keep,
+        _ => AbortedRead::Fail,
+    }
+}
+
+fn is_aborted(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(ERROR_OPERATION_ABORTED as i32)
+}
+
+fn pending_or_error() -> Result<()> {
+    let err = Error::last_os_error();
+    match err.raw_os_error() {
+        Some(code) if code == ERROR_IO_PENDING as i32 => Ok(()),
+        _ => Err(error::from_io(err)),
+    }
+}
+
+impl Drop for COMPort {
+    fn drop(&mut self) {
+        // The OS must be done with the buffers before they are freed
+        if self.read.pending {
+            let _ = self.read.overlapped.cancel(self.as_raw_handle());
+        }
+        if self.flush_on_drop {
+            let handle = self.as_raw_handle();
+            if self.write.pending {
+                let _ = self.write.overlapped.wait(handle);
+                self.write.pending = false;
+            }
+            unsafe { FlushFileBuffers(handle) };
         }
+        self.cancel_write();
+    }
+}
+
+/// Shows the path and, read back from the driver, the line settings; the
+/// handle is left out
+impl fmt::Debug for COMPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("COMPort");
+        debug.field("path", &self.path);
+        // The device may be gone, which leaves just the path
+        if let Ok(config) = self.effective_config() {
+            debug.field("config", &config);
+        }
+        debug.finish_non_exhaustive()
     }
 }
 
 impl AsRawHandle for COMPort {
     fn as_raw_handle(&self) -> RawHandle {
-        self.pipe.as_raw_handle()
+        self.handle.as_raw_handle()
     }
 }
 
 impl AsyncRead for COMPort {
     fn poll_read(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
-        buf: &mut tokio::io::ReadBuf<'_>,
+        buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.pipe).poll_read(cx, buf)
+        let this = self.get_mut();
+        let handle = this.as_raw_handle();
+        this.access.check_read()?;
+
+        loop {
+            this.take_input_purge();
+            let read = &mut this.read;
+
+            // Hand out what is left over from the last overlapped read first
+            if read.pos < read.buf.len() {
+                let len = buf.remaining().min(read.buf.len() - read.pos);
+                buf.put_slice(&read.buf[read.pos..read.pos + len]);
+                read.pos += len;
+                return Poll::Ready(Ok(()));
+            }
+
+            if buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            if !read.pending {
+                let mut available = this.comm_status()?.cbInQue as usize;
+                if this.apply_overrun_policy()? {
+                    available = 0;
+                }
+                let read = &mut this.read;
+
+                // Coalescing reads wait for the line to go quiet, which only
+                // an overlapped read can do without blocking
+                if available > 0 && !this.coalesce {
+                    // The bytes are already buffered by the driver, so the read
+                    // completes at once and can go straight into the caller's
+                    // buffer without zeroing or copying it
+                    let unfilled = unsafe { buf.unfilled_mut() };
+                    let len = unfilled.len().min(available) as u32;
+                    let ptr = unfilled.as_mut_ptr() as *mut u8;
+                    read_file(handle, ptr, len, read.overlapped.start())?;
+                    let n = read.overlapped.wait(handle).map_err(error::from_io)? as usize;
+                    unsafe { buf.assume_init(n) };
+                    buf.advance(n);
+                    return Poll::Ready(Ok(()));
+                }
+
+                // Nothing buffered yet, wait for the first byte in a buffer we
+                // own, as the caller's may be gone by the time it arrives.
+                // Only the spare capacity is used, so it is never zeroed.
+                read.buf.clear();
+                read.buf.reserve(buf.remaining());
+                read.pos = 0;
+
+                let spare = read.buf.spare_capacity_mut();
+                let len = spare.len().min(buf.remaining()) as u32;
+                let ptr = spare.as_mut_ptr() as *mut u8;
+                read_file(handle, ptr, len, read.overlapped.start())?;
+                read.pending = true;
+            }
+
+            let read = &mut this.read;
+            let result = match read.overlapped.poll(handle, cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(result) => result,
+            };
+            let n = this.finish_read(result)?;
+            let read = &mut this.read;
+
+            unsafe { read.buf.set_len(n) };
+            let status = this.comm_status();
+            this.apply_overrun_policy()?;
+
+            // A read that saw no bytes at all was ended by the read timeouts,
+            // or some drivers complete reads that way once the device is
+            // unplugged. Only a device that is still there answers the status
+            // queries; if it does, loop to wait for the next byte again.
+            if n == 0
+                && let Err(e) = status.and_then(|_| this.modem_status())
+            {
+                return Poll::Ready(Err(gone(e).into()));
+            }
+        }
     }
 }
 
 impl AsyncWrite for COMPort {
+    /// Queue `buf` for transmission
+    ///
+    /// The data is copied and handed to the driver, so the write is reported
+    /// complete right away. Only one write is in flight at a time; a failure
+    /// of it is reported by the next write or flush.
     fn poll_write(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.pipe).poll_write(cx, buf)
+        self.poll_write_vectored(cx, &[io::IoSlice::new(buf)])
+    }
+
+    /// Queue all of `bufs` for transmission as one write
+    ///
+    /// `WriteFile` cannot gather, so the slices are copied into one buffer,
+    /// which a write copies anyway.
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.access.check_write()?;
+        std::task::ready!(this.poll_write_complete(cx))?;
+
+        if let Some(rs485) = &mut this.rs485 {
+            rs485.start_write()?;
+        }
+
+        let handle = this.as_raw_handle();
+        let write = &mut this.write;
+        write.buf.clear();
+        for buf in bufs {
+            write.buf.extend_from_slice(buf);
+        }
+
+        let len = write.buf.len();
+        write_file(
+            handle,
+            write.buf.as_ptr(),
+            len as u32,
+            write.overlapped.start(),
+        )?;
+
+        write.pending = true;
+        if len < this.sync_threshold
+            && let Poll::Ready(result) = write.overlapped.check(handle)
+        {
+            write.pending = false;
+            result.map_err(error::from_io)?;
+        }
+
+        Poll::Ready(Ok(len))
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.pipe).poll_flush(cx)
+    fn is_write_vectored(&self) -> bool {
+        true
     }
 
-    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.pipe).poll_shutdown(cx)
+    /// Wait for the in-flight write to complete, and with
+    /// [`crate::SerialPortBuilder::rs485_manual`] for it to be sent
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        std::task::ready!(this.poll_write_complete(cx))?;
+        match &mut this.rs485 {
+            Some(rs485) => rs485.poll_release(cx).map_err(Into::into),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
     }
 }
 
@@ -149,90 +1399,110 @@ impl SerialPort for COMPort {
     }
 
     fn data_bits(&self) -> Result<DataBits> {
-        let dcb = dcb::get_dcb(self.as_raw_handle())?;
-        match dcb.ByteSize {
-            5 => Ok(DataBits::Five),
-            6 => Ok(DataBits::Six),
-            7 => Ok(DataBits::Seven),
-            8 => Ok(DataBits::Eight),
-            _ => Ok(DataBits::Unknown),
-        }
+        Ok(dcb::data_bits(&dcb::get_dcb(self.as_raw_handle())?))
     }
 
     fn flow_control(&self) -> Result<FlowControl> {
-        let dcb = dcb::get_dcb(self.as_raw_handle())?;
-        if dcb.fOutxCtsFlow() || dcb.fRtsControl() != dcb::RtsControl::Disable {
-            Ok(FlowControl::Hardware)
-        } else if dcb.fOutX() || dcb.fInX() {
-            Ok(FlowControl::Software)
-        } else {
-            Ok(FlowControl::None)
-        }
+        Ok(dcb::flow_control(&dcb::get_dcb(self.as_raw_handle())?))
+    }
+
+    fn flow_control_state(&self) -> Result<FlowControlState> {
+        Ok(dcb::flow_control_state(&dcb::get_dcb(
+            self.as_raw_handle(),
+        )?))
     }
 
     fn parity(&self) -> Result<Parity> {
-        let dcb = dcb::get_dcb(self.as_raw_handle())?;
-        match dcb.Parity {
-            ODDPARITY => Ok(Parity::Odd),
-            EVENPARITY => Ok(Parity::Even),
-            NOPARITY => Ok(Parity::None),
-            _ => Ok(Parity::Unknown),
-        }
+        Ok(dcb::parity(&dcb::get_dcb(self.as_raw_handle())?))
     }
 
     fn stop_bits(&self) -> Result<StopBits> {
+        Ok(dcb::stop_bits(&dcb::get_dcb(self.as_raw_handle())?))
+    }
+
+    fn effective_config(&self) -> Result<PortConfig> {
         let dcb = dcb::get_dcb(self.as_raw_handle())?;
-        match dcb.StopBits {
-            TWOSTOPBITS => Ok(StopBits::Two),
-            ONESTOPBIT => Ok(StopBits::One),
-            ONE5STOPBITS => Ok(StopBits::OnePointFive),
-            _ => Ok(StopBits::Unknown),
-        }
+        Ok(PortConfig {
+            baud_rate: dcb.BaudRate,
+            data_bits: dcb::data_bits(&dcb),
+            flow_control: dcb::flow_control(&dcb),
+            parity: dcb::parity(&dcb),
+            stop_bits: dcb::stop_bits(&dcb),
+        })
+    }
+
+    fn to_builder(&self) -> Result<SerialPortBuilder> {
+        let dcb = dcb::get_dcb(self.as_raw_handle())?;
+        let (xon_lim, xoff_lim) = dcb::flow_limits(&dcb);
+        Ok(crate::new(&self.path, dcb.BaudRate)
+            .data_bits(dcb::data_bits(&dcb))
+            .flow_control(dcb::flow_control(&dcb))
+            .parity(dcb::parity(&dcb))
+            .stop_bits(dcb::stop_bits(&dcb))
+            .flow_limits(xon_lim, xoff_lim)
+            .error_replacement(dcb::error_replacement(&dcb))
+            .access(self.access))
+    }
+
+    fn set_config_atomic(&mut self, config: &PortConfig) -> Result<()> {
+        self.check_baud_rate(config.baud_rate)?;
+        let saved = dcb::get_dcb(self.as_raw_handle())?;
+        let mut dcb = saved;
+        dcb::set_baud_rate(&mut dcb, config.baud_rate);
+        dcb::set_data_bits(&mut dcb, config.data_bits)?;
+        dcb::set_flow_control(&mut dcb, config.flow_control)?;
+        dcb::set_parity(&mut dcb, config.parity)?;
+        dcb::set_stop_bits(&mut dcb, config.stop_bits)?;
+
+        self.write_dcb(dcb).inspect_err(|_| {
+            let _ = dcb::set_dcb(self.as_raw_handle(), saved);
+        })
     }
 
     fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        self.check_baud_rate(baud_rate)?;
         let mut dcb = dcb::get_dcb(self.as_raw_handle())?;
         dcb::set_baud_rate(&mut dcb, baud_rate);
-        dcb::set_dcb(self.as_raw_handle(), dcb)
+        self.write_dcb(dcb)
     }
 
     fn set_data_bits(&mut self, data_bits: DataBits) -> Result<()> {
         let mut dcb = dcb::get_dcb(self.as_raw_handle())?;
         dcb::set_data_bits(&mut dcb, data_bits)?;
-        dcb::set_dcb(self.as_raw_handle(), dcb)
+        self.write_dcb(dcb)
     }
 
     fn set_flow_control(&mut self, flow_control: FlowControl) -> Result<()> {
         let mut dcb = dcb::get_dcb(self.as_raw_handle())?;
         dcb::set_flow_control(&mut dcb, flow_control)?;
-        dcb::set_dcb(self.as_raw_handle(), dcb)
+        self.write_dcb(dcb)
     }
 
     fn set_parity(&mut self, parity: Parity) -> Result<()> {
         let mut dcb = dcb::get_dcb(self.as_raw_handle())?;
         dcb::set_parity(&mut dcb, parity)?;
-        dcb::set_dcb(self.as_raw_handle(), dcb)
+        self.write_dcb(dcb)
     }
 
     fn set_stop_bits(&mut self, stop_bits: StopBits) -> Result<()> {
         let mut dcb = dcb::get_dcb(self.as_raw_handle())?;
         dcb::set_stop_bits(&mut dcb, stop_bits)?;
-        dcb::set_dcb(self.as_raw_handle(), dcb)
+        self.write_dcb(dcb)
     }
 
     fn set_rts(&mut self, level: bool) -> Result<()> {
         if level {
-            self.escape_comm_function(SETRTS)
+            self.escape_comm(SETRTS)
         } else {
-            self.escape_comm_function(CLRRTS)
+            self.escape_comm(CLRRTS)
         }
     }
 
     fn set_dtr(&mut self, level: bool) -> Result<()> {
         if level {
-            self.escape_comm_function(SETDTR)
+            self.escape_comm(SETDTR)
         } else {
-            self.escape_comm_function(CLRDTR)
+            self.escape_comm(CLRDTR)
         }
     }
 
@@ -246,7 +1516,20 @@ impl SerialPort for COMPort {
                 return Ok(());
             }
         }
-        Err(Error::last_os_error().into())
+        Err(error::last_os_error())
+    }
+
+    fn break_state(&self) -> Result<bool> {
+        Err(crate::Error::NotImplemented)
+    }
+
+    fn set_mark(&mut self, level: bool) -> Result<()> {
+        if level {
+            self.set_break(false)?;
+            self.escape_comm(SETXOFF)
+        } else {
+            self.escape_comm(SETXON)
+        }
     }
 
     fn cts(&mut self) -> Result<bool> {
@@ -265,39 +1548,72 @@ impl SerialPort for COMPort {
         self.read_pin(MS_RLSD_ON)
     }
 
+    /// Also counts the bytes held back for the next read, e.g. by
+    /// [`COMPort::peek`]
     fn bytes_to_read(&self) -> Result<u32> {
-        let mut errors: u32 = 0;
-        let mut comstat = MaybeUninit::uninit();
-
-        if unsafe { ClearCommError(self.as_raw_handle(), &mut errors, comstat.as_mut_ptr()) != 0 } {
-            unsafe { Ok(comstat.assume_init().cbInQue) }
-        } else {
-            Err(Error::last_os_error().into())
-        }
+        let queued = self.comm_status()?.cbInQue;
+        let held = match self.input_purged.load(Ordering::Relaxed) {
+            0 => self.read.buf.len() - self.read.pos,
+            _ => 0,
+        };
+        Ok(queued + held as u32)
     }
 
     fn bytes_to_write(&self) -> Result<u32> {
-        let mut errors: u32 = 0;
-        let mut comstat = MaybeUninit::uninit();
-
-        if unsafe { ClearCommError(self.as_raw_handle(), &mut errors, comstat.as_mut_ptr()) != 0 } {
-            unsafe { Ok(comstat.assume_init().cbOutQue) }
-        } else {
-            Err(Error::last_os_error().into())
-        }
+        Ok(self.comm_status()?.cbOutQue)
     }
 
     fn clear(&self, buffer_to_clear: Clear) -> Result<()> {
-        let buffer_flags = match buffer_to_clear {
-            Clear::Input => PURGE_RXABORT | PURGE_RXCLEAR,
-            Clear::Output => PURGE_TXABORT | PURGE_TXCLEAR,
-            Clear::All => PURGE_RXABORT | PURGE_RXCLEAR | PURGE_TXABORT | PURGE_TXCLEAR,
-        };
+        self.clear_with(PurgeFlags::from(buffer_to_clear))
+    }
+}
 
-        if unsafe { PurgeComm(self.as_raw_handle(), buffer_flags) != 0 } {
-            Ok(())
-        } else {
-            Err(Error::last_os_error().into())
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use windows_sys::Win32::Devices::Communication::{CE_FRAME, CE_OVERRUN, CE_RXOVER};
+
+    use super::{AbortedRead, aborted_read, gone, overrun_action};
+    use crate::{Error, OverrunPolicy};
+
+    #[test]
+    fn test_aborted_read() {
+        // Without a line error, only a purge aborts a read
+        assert_eq!(aborted_read(0, false), AbortedRead::Drop);
+        assert_eq!(aborted_read(0, true), AbortedRead::Drop);
+
+        assert_eq!(aborted_read(CE_RXOVER, true), AbortedRead::Keep);
+        assert_eq!(aborted_read(CE_RXOVER | CE_FRAME, true), AbortedRead::Keep);
+        assert_eq!(aborted_read(CE_RXOVER, false), AbortedRead::Fail);
+        assert_eq!(aborted_read(CE_OVERRUN, true), AbortedRead::Fail);
+        assert_eq!(aborted_read(CE_FRAME, false), AbortedRead::Fail);
+    }
+
+    #[test]
+    fn test_gone() {
+        let err = gone(Error::Io(io::Error::from_raw_os_error(2)));
+        assert!(matches!(err, Error::Disconnected(Some(_))));
+        assert_eq!(err.raw_os_error(), Some(2));
+
+        assert!(matches!(gone(Error::Overrun), Error::Overrun));
+    }
+
+    #[test]
+    fn test_overrun_action() {
+        for policy in [
+            OverrunPolicy::Ignore,
+            OverrunPolicy::Error,
+            OverrunPolicy::Clear,
+        ] {
+            assert!(!overrun_action(policy, false).unwrap());
         }
+
+        assert!(!overrun_action(OverrunPolicy::Ignore, true).unwrap());
+        assert!(matches!(
+            overrun_action(OverrunPolicy::Error, true),
+            Err(Error::Overrun)
+        ));
+        assert!(overrun_action(OverrunPolicy::Clear, true).unwrap());
     }
 }
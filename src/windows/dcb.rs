@@ -1,11 +1,10 @@
-use std::io::Error;
 use windows_sys::Win32::Devices::Communication::{
-    DCB, EVENPARITY, GetCommState, NOPARITY, ODDPARITY, ONE5STOPBITS, ONESTOPBIT, SetCommState,
-    TWOSTOPBITS,
+    DCB, EVENPARITY, GetCommState, MARKPARITY, NOPARITY, ODDPARITY, ONE5STOPBITS, ONESTOPBIT,
+    SPACEPARITY, SetCommState, TWOSTOPBITS,
 };
 use windows_sys::Win32::Foundation::HANDLE;
 
-use crate::{DataBits, FlowControl, Parity, Result, StopBits};
+use crate::{DataBits, FlowControl, FlowControlState, Parity, Result, StopBits, windows::error};
 
 #[allow(dead_code)]
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -41,6 +40,9 @@ pub(crate) trait BitOperation {
     fn set_fAbortOnError(&mut self, value: bool);
 
     fn fOutxCtsFlow(&self) -> bool;
+    fn fOutxDsrFlow(&self) -> bool;
+    fn fDtrControl(&self) -> DtrControl;
+    fn fErrorChar(&self) -> bool;
     fn fRtsControl(&self) -> RtsControl;
     fn fOutX(&self) -> bool;
     fn fInX(&self) -> bool;
@@ -151,6 +153,23 @@ impl BitOperation for DCB {
         (self._bitfield & (1 << 2)) != 0
     }
 
+    fn fOutxDsrFlow(&self) -> bool {
+        (self._bitfield & (1 << 3)) != 0
+    }
+
+    fn fDtrControl(&self) -> DtrControl {
+        match (self._bitfield >> 4) & 0b11 {
+            0 => DtrControl::Disable,
+            1 => DtrControl::Enable,
+            // 3 is reserved, treat it like the handshake it neighbours
+            _ => DtrControl::Handshake,
+        }
+    }
+
+    fn fErrorChar(&self) -> bool {
+        (self._bitfield & (1 << 10)) != 0
+    }
+
     fn fRtsControl(&self) -> RtsControl {
         let bits = (self._bitfield >> 12) & 0b11;
         match bits {
@@ -193,7 +212,7 @@ pub(crate) fn get_dcb(handle: HANDLE) -> Result<DCB> {
     if unsafe { GetCommState(handle, &mut dcb) } != 0 {
         Ok(dcb)
     } else {
-        Err(Error::last_os_error().into())
+        Err(error::last_os_error())
     }
 }
 
@@ -201,10 +220,144 @@ pub(crate) fn set_dcb(handle: HANDLE, mut dcb: DCB) -> Result<()> {
     if unsafe { SetCommState(handle, &mut dcb as *mut _) != 0 } {
         Ok(())
     } else {
-        Err(Error::last_os_error().into())
+        Err(error::last_os_error())
+    }
+}
+
+/// Restores the saved DCB when dropped, so a temporary change is undone even
+/// if the future making it fails or is cancelled
+pub(crate) struct RestoreGuard {
+    handle: HANDLE,
+    saved: DCB,
+}
+
+unsafe impl Send for RestoreGuard {}
+
+impl RestoreGuard {
+    pub(crate) fn new(handle: HANDLE) -> Result<RestoreGuard> {
+        Ok(RestoreGuard {
+            handle,
+            saved: get_dcb(handle)?,
+        })
+    }
+
+    pub(crate) fn restore(self) -> Result<()> {
+        let result = set_dcb(self.handle, self.saved);
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        let _ = set_dcb(self.handle, self.saved);
+    }
+}
+
+/// Read the DCB back and check the driver applied every field of `expected`
+pub(crate) fn verify_dcb(handle: HANDLE, expected: &DCB) -> Result<()> {
+    // Bits above fAbortOnError are reserved
+    const FLAGS: u32 = (1 << 15) - 1;
+
+    let actual = get_dcb(handle)?;
+    let mismatch = if actual.BaudRate != expected.BaudRate {
+        Some(("BaudRate", expected.BaudRate, actual.BaudRate))
+    } else if actual.ByteSize != expected.ByteSize {
+        Some(("ByteSize", expected.ByteSize.into(), actual.ByteSize.into()))
+    } else if actual.Parity != expected.Parity {
+        Some(("Parity", expected.Parity.into(), actual.Parity.into()))
+    } else if actual.StopBits != expected.StopBits {
+        Some(("StopBits", expected.StopBits.into(), actual.StopBits.into()))
+    } else if actual.XonLim != expected.XonLim {
+        Some(("XonLim", expected.XonLim.into(), actual.XonLim.into()))
+    } else if actual.XoffLim != expected.XoffLim {
+        Some(("XoffLim", expected.XoffLim.into(), actual.XoffLim.into()))
+    } else if actual.ErrorChar != expected.ErrorChar {
+        Some((
+            "ErrorChar",
+            expected.ErrorChar as u32,
+            actual.ErrorChar as u32,
+        ))
+    } else if actual._bitfield & FLAGS != expected._bitfield & FLAGS {
+        Some((
+            "flags",
+            expected._bitfield & FLAGS,
+            actual._bitfield & FLAGS,
+        ))
+    } else {
+        None
+    };
+
+    match mismatch {
+        Some((field, expected, actual)) => Err(crate::Error::InvalidArgument(format!(
+            "{field} {expected} was not applied by the driver, it reports {actual}"
+        ))),
+        None => Ok(()),
     }
 }
 
+pub(crate) fn data_bits(dcb: &DCB) -> DataBits {
+    match dcb.ByteSize {
+        5 => DataBits::Five,
+        6 => DataBits::Six,
+        7 => DataBits::Seven,
+        8 => DataBits::Eight,
+        _ => DataBits::Unknown,
+    }
+}
+
+pub(crate) fn flow_control(dcb: &DCB) -> FlowControl {
+    flow_control_state(dcb).mode()
+}
+
+/// RTS held on, or toggled around writes, is no handshake; the same goes
+/// for DTR
+pub(crate) fn flow_control_state(dcb: &DCB) -> FlowControlState {
+    FlowControlState {
+        rts_cts: dcb.fOutxCtsFlow() || dcb.fRtsControl() == RtsControl::Handshake,
+        dsr_dtr: dcb.fOutxDsrFlow() || dcb.fDtrControl() == DtrControl::Handshake,
+        xon_xoff: dcb.fOutX() || dcb.fInX(),
+    }
+}
+
+pub(crate) fn parity(dcb: &DCB) -> Parity {
+    match dcb.Parity {
+        ODDPARITY => Parity::Odd,
+        EVENPARITY => Parity::Even,
+        NOPARITY => Parity::None,
+        MARKPARITY => Parity::Mark,
+        SPACEPARITY => Parity::Space,
+        _ => Parity::Unknown,
+    }
+}
+
+pub(crate) fn stop_bits(dcb: &DCB) -> StopBits {
+    match dcb.StopBits {
+        TWOSTOPBITS => StopBits::Two,
+        ONESTOPBIT => StopBits::One,
+        ONE5STOPBITS => StopBits::OnePointFive,
+        _ => StopBits::Unknown,
+    }
+}
+
+pub(crate) fn flow_limits(dcb: &DCB) -> (u16, u16) {
+    (dcb.XonLim, dcb.XoffLim)
+}
+
+pub(crate) fn set_flow_limits(dcb: &mut DCB, xon_lim: u16, xoff_lim: u16) {
+    dcb.XonLim = xon_lim;
+    dcb.XoffLim = xoff_lim;
+}
+
+pub(crate) fn error_replacement(dcb: &DCB) -> Option<u8> {
+    dcb.fErrorChar().then_some(dcb.ErrorChar as u8)
+}
+
+pub(crate) fn set_error_replacement(dcb: &mut DCB, replacement: Option<u8>) {
+    dcb.set_fErrorChar(replacement.is_some());
+    dcb.ErrorChar = replacement.unwrap_or(0) as _;
+}
+
 pub(crate) fn set_baud_rate(dcb: &mut DCB, baud_rate: u32) {
     dcb.BaudRate = baud_rate;
 }
@@ -225,6 +378,8 @@ pub(crate) fn set_parity(dcb: &mut DCB, parity: Parity) -> Result<()> {
         Parity::None => NOPARITY,
         Parity::Odd => ODDPARITY,
         Parity::Even => EVENPARITY,
+        Parity::Mark => MARKPARITY,
+        Parity::Space => SPACEPARITY,
         _ => return Err(crate::Error::InvalidArgument("Parity::Unknown".to_owned())),
     };
 
@@ -266,3 +421,133 @@ pub(crate) fn set_flow_control(dcb: &mut DCB, flow_control: FlowControl) -> Resu
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use windows_sys::Win32::Devices::Communication::DCB;
+
+    use super::{
+        BitOperation, DtrControl, RtsControl, data_bits, error_replacement, flow_control,
+        flow_control_state, flow_limits, set_error_replacement, set_flow_control, set_flow_limits,
+        set_parity,
+    };
+    use crate::{DataBits, FlowControl, FlowControlState, Parity};
+
+    #[test]
+    fn test_parity_round_trip() {
+        for parity in [
+            Parity::None,
+            Parity::Odd,
+            Parity::Even,
+            Parity::Mark,
+            Parity::Space,
+        ] {
+            let mut dcb = DCB::default();
+            set_parity(&mut dcb, parity).unwrap();
+            assert_eq!(super::parity(&dcb), parity);
+        }
+
+        let dcb = DCB {
+            Parity: 5,
+            ..DCB::default()
+        };
+        assert_eq!(super::parity(&dcb), Parity::Unknown);
+    }
+
+    #[test]
+    fn test_data_bits() {
+        let dcb = |size| DCB {
+            ByteSize: size,
+            ..DCB::default()
+        };
+        assert_eq!(data_bits(&dcb(5)), DataBits::Five);
+        assert_eq!(data_bits(&dcb(8)), DataBits::Eight);
+        assert_eq!(data_bits(&dcb(9)), DataBits::Unknown);
+        assert_eq!(data_bits(&dcb(4)), DataBits::Unknown);
+    }
+
+    #[test]
+    fn test_flow_control_round_trip() {
+        for mode in [
+            FlowControl::None,
+            FlowControl::Software,
+            FlowControl::Hardware,
+        ] {
+            let mut dcb = DCB::default();
+            set_flow_control(&mut dcb, mode).unwrap();
+            assert_eq!(flow_control(&dcb), mode);
+            assert_eq!(flow_control_state(&dcb), FlowControlState::from(mode));
+        }
+    }
+
+    #[test]
+    fn test_flow_control_state() {
+        let state = |rts_cts, dsr_dtr, xon_xoff| FlowControlState {
+            rts_cts,
+            dsr_dtr,
+            xon_xoff,
+        };
+
+        // Lines merely held on are not flow control
+        let mut dcb = DCB::default();
+        dcb.set_fRtsControl(RtsControl::Enable);
+        dcb.set_fDtrControl(DtrControl::Enable);
+        assert_eq!(flow_control_state(&dcb), state(false, false, false));
+        assert_eq!(flow_control(&dcb), FlowControl::None);
+        dcb.set_fRtsControl(RtsControl::Toggle);
+        assert_eq!(flow_control(&dcb), FlowControl::None);
+
+        // Either direction of a mechanism counts
+        let mut dcb = DCB::default();
+        dcb.set_fRtsControl(RtsControl::Handshake);
+        assert_eq!(flow_control_state(&dcb), state(true, false, false));
+        let mut dcb = DCB::default();
+        dcb.set_fOutxCtsFlow(true);
+        assert_eq!(flow_control_state(&dcb), state(true, false, false));
+
+        let mut dcb = DCB::default();
+        dcb.set_fOutxDsrFlow(true);
+        assert_eq!(flow_control_state(&dcb), state(false, true, false));
+        assert_eq!(flow_control(&dcb), FlowControl::Hardware);
+        let mut dcb = DCB::default();
+        dcb.set_fDtrControl(DtrControl::Handshake);
+        assert_eq!(flow_control_state(&dcb), state(false, true, false));
+
+        let mut dcb = DCB::default();
+        dcb.set_fInX(true);
+        assert_eq!(flow_control_state(&dcb), state(false, false, true));
+        assert_eq!(flow_control(&dcb), FlowControl::Software);
+
+        // Hardware and software at once fit no single mode
+        dcb.set_fOutxCtsFlow(true);
+        dcb.set_fOutxDsrFlow(true);
+        assert_eq!(flow_control_state(&dcb), state(true, true, true));
+        assert_eq!(flow_control(&dcb), FlowControl::Unknown);
+    }
+
+    #[test]
+    fn test_flow_limits() {
+        let mut dcb = DCB::default();
+        set_flow_limits(&mut dcb, 512, 1024);
+        assert_eq!(flow_limits(&dcb), (512, 1024));
+        assert_eq!((dcb.XonLim, dcb.XoffLim), (512, 1024));
+    }
+
+    #[test]
+    fn test_error_replacement() {
+        let mut dcb = DCB::default();
+        assert_eq!(error_replacement(&dcb), None);
+
+        set_error_replacement(&mut dcb, Some(b'?'));
+        assert_eq!(error_replacement(&dcb), Some(b'?'));
+        assert_eq!(dcb._bitfield & (1 << 10), 1 << 10);
+
+        // A null byte is a valid replacement, fNull stays off
+        set_error_replacement(&mut dcb, Some(0));
+        assert_eq!(error_replacement(&dcb), Some(0));
+
+        set_error_replacement(&mut dcb, None);
+        assert_eq!(error_replacement(&dcb), None);
+        assert_eq!(dcb.ErrorChar, 0);
+    }
+}
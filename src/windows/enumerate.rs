@@ -1,9 +1,14 @@
-use std::ptr::{null, null_mut};
+use std::{
+    io,
+    ops::ControlFlow,
+    ptr::{null, null_mut},
+};
 
 use windows_sys::{
     Win32::{
         Devices::DeviceAndDriverInstallation::{
-            DICS_FLAG_GLOBAL, DIGCF_PRESENT, DIREG_DEV, SP_DEVINFO_DATA, SPDRP_FRIENDLYNAME,
+            DICS_FLAG_GLOBAL, DIGCF_PRESENT, DIREG_DEV, HDEVINFO, SETUP_DI_REGISTRY_PROPERTY,
+            SP_DEVINFO_DATA, SPDRP_ENUMERATOR_NAME, SPDRP_FRIENDLYNAME, SPDRP_SERVICE,
             SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInfo, SetupDiGetClassDevsW,
             SetupDiGetDeviceRegistryPropertyW, SetupDiOpenDevRegKey,
         },
@@ -13,7 +18,7 @@ use windows_sys::{
     core::GUID,
 };
 
-use crate::{PortInfo, Result};
+use crate::{PortInfo, PortType, Result};
 
 fn as_utf16(utf8: &str) -> Vec<u16> {
     utf8.encode_utf16().chain(Some(0)).collect()
@@ -32,11 +37,151 @@ const GUID_DEVCLASS_PORTS: GUID = GUID {
     data4: [0xbf, 0xc1, 0x08, 0x00, 0x2b, 0xe1, 0x03, 0x18],
 };
 
+/// Read a string property of the device, if it has one
+unsafe fn device_property(
+    ports: HDEVINFO,
+    info: &SP_DEVINFO_DATA,
+    property: SETUP_DI_REGISTRY_PROPERTY,
+) -> Option<String> {
+    let mut buffer = [0u16; 256];
+
+    if unsafe {
+        SetupDiGetDeviceRegistryPropertyW(
+            ports,
+            info,
+            property,
+            null_mut(),
+            buffer.as_mut_ptr() as *mut u8,
+            (buffer.len() * 2) as u32,
+            null_mut(),
+        )
+    } != 0
+    {
+        Some(from_utf16_lossy_trimmed(&buffer))
+    } else {
+        None
+    }
+}
+
+/// Read the `PortName` the device is opened by from its registry key
+unsafe fn port_name(ports: HDEVINFO, info: &SP_DEVINFO_DATA) -> Option<String> {
+    let hkey =
+        unsafe { SetupDiOpenDevRegKey(ports, info, DICS_FLAG_GLOBAL, 0, DIREG_DEV, KEY_READ) };
+    if hkey == INVALID_HANDLE_VALUE {
+        return None;
+    }
+
+    let mut buffer = [0u16; 64];
+    let mut size = (buffer.len() * 2) as u32;
+    let result = unsafe {
+        RegQueryValueExW(
+            hkey,
+            as_utf16("PortName").as_ptr(),
+            null_mut(),
+            null_mut(),
+            buffer.as_mut_ptr() as *mut u8,
+            &mut size,
+        )
+    };
+    unsafe { RegCloseKey(hkey) };
+
+    let path = from_utf16_lossy_trimmed(&buffer);
+    (result == 0 && !path.is_empty()).then_some(path)
+}
+
+/// Parse the port name from the `(COMn)` suffix of a friendly name such as
+/// `USB Serial Device (COM7)`
+fn port_name_from_friendly_name(name: &str) -> Option<String> {
+    let (_, suffix) = name.trim_end().strip_suffix(')')?.rsplit_once('(')?;
+    let number = suffix.strip_prefix("COM")?;
+
+    (!number.is_empty() && number.bytes().all(|b| b.is_ascii_digit())).then(|| suffix.to_owned())
+}
+
+/// Tell virtual ports from physical ones by the bus that enumerated the device
+/// and the driver service running it
+fn port_type(enumerator: &str, service: &str) -> PortType {
+    const VIRTUAL_ENUMERATORS: [&str; 3] = ["BTHENUM", "COM0COM", "ROOT"];
+    const VIRTUAL_SERVICES: [&str; 4] = ["com0com", "vspd", "evsbc", "bthmodem"];
+    const PHYSICAL_ENUMERATORS: [&str; 5] = ["ACPI", "PCI", "USB", "FTDIBUS", "SERENUM"];
+
+    let is = |list: &[&str], value: &str| list.iter().any(|x| x.eq_ignore_ascii_case(value));
+
+    if is(&VIRTUAL_ENUMERATORS, enumerator) || is(&VIRTUAL_SERVICES, service) {
+        PortType::Virtual
+    } else if is(&PHYSICAL_ENUMERATORS, enumerator) {
+        PortType::Physical
+    } else {
+        PortType::Unknown
+    }
+}
+
 pub fn available_ports() -> Result<Vec<PortInfo>> {
     let mut infos = Vec::new();
+    for_each_port(|info| infos.push(info))?;
+    Ok(infos)
+}
 
+/// Enumerate the ports, handing each to `found` as soon as it is read, so a
+/// caller giving up on a slow device still has the ones before it
+pub(crate) fn for_each_port(mut found: impl FnMut(PortInfo)) -> Result<()> {
+    for_each_device(|ports, info, path| {
+        let mut _info = PortInfo {
+            path,
+            ..PortInfo::default()
+        };
+
+        // Friendly name
+        if let Some(name) = unsafe { device_property(ports, info, SPDRP_FRIENDLYNAME) } {
+            _info.name = name;
+        }
+
+        // Port type
+        let enumerator = unsafe { device_property(ports, info, SPDRP_ENUMERATOR_NAME) };
+        let service = unsafe { device_property(ports, info, SPDRP_SERVICE) };
+        _info.port_type = port_type(
+            enumerator.as_deref().unwrap_or_default(),
+            service.as_deref().unwrap_or_default(),
+        );
+
+        found(_info);
+        ControlFlow::Continue(())
+    })
+}
+
+/// Name of the driver service running the port at `path`, e.g. `usbser`
+pub(crate) fn driver_service(path: &str) -> Result<String> {
+    let mut service = None;
+    for_each_device(|ports, info, port| {
+        if !port.eq_ignore_ascii_case(path) {
+            return ControlFlow::Continue(());
+        }
+        service = unsafe { device_property(ports, info, SPDRP_SERVICE) };
+        ControlFlow::Break(())
+    })?;
+
+    service.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no driver service found for {path}"),
+        )
+        .into()
+    })
+}
+
+/// Walk the present ports, handing `f` each device with the path it is
+/// opened by
+///
+/// Devices without a path are skipped, as they cannot be opened, e.g. when
+/// their registry key is missing or access to it is denied.
+fn for_each_device(
+    mut f: impl FnMut(HDEVINFO, &SP_DEVINFO_DATA, String) -> ControlFlow<()>,
+) -> Result<()> {
     unsafe {
         let ports = SetupDiGetClassDevsW(&GUID_DEVCLASS_PORTS, null(), null_mut(), DIGCF_PRESENT);
+        if ports == INVALID_HANDLE_VALUE as HDEVINFO {
+            return Err(io::Error::last_os_error().into());
+        }
 
         let mut info = SP_DEVINFO_DATA {
             cbSize: size_of::<SP_DEVINFO_DATA>() as u32,
@@ -51,50 +196,54 @@ pub fn available_ports() -> Result<Vec<PortInfo>> {
                 }
             }
 
-            let mut buffer = [0u16; 64];
-
-            let mut _info = PortInfo::default();
-
-            // Path
-            let hkey = SetupDiOpenDevRegKey(ports, &info, DICS_FLAG_GLOBAL, 0, DIREG_DEV, KEY_READ);
-
-            if hkey != INVALID_HANDLE_VALUE {
-                let mut _size = (buffer.len() * 2) as u32;
-
-                if RegQueryValueExW(
-                    hkey,
-                    as_utf16("PortName").as_ptr(),
-                    null_mut(),
-                    null_mut(),
-                    buffer.as_mut_ptr() as *mut u8,
-                    &mut _size,
-                ) == 0
-                {
-                    _info.path = from_utf16_lossy_trimmed(&buffer);
-                }
-                RegCloseKey(hkey);
-            }
+            index += 1;
 
-            // Friendly name
-            if SetupDiGetDeviceRegistryPropertyW(
-                ports,
-                &info,
-                SPDRP_FRIENDLYNAME,
-                null_mut(),
-                buffer.as_mut_ptr() as *mut u8,
-                (buffer.len() * 2) as u32,
-                null_mut(),
-            ) != 0
-            {
-                _info.name = from_utf16_lossy_trimmed(&buffer);
+            // Some drivers only give the path as part of the friendly name
+            let path = port_name(ports, &info).or_else(|| {
+                device_property(ports, &info, SPDRP_FRIENDLYNAME)
+                    .and_then(|name| port_name_from_friendly_name(&name))
+            });
+            let Some(path) = path else {
+                continue;
+            };
+
+            if f(ports, &info, path).is_break() {
+                break;
             }
-
-            index += 1;
-            infos.push(_info);
         }
 
         SetupDiDestroyDeviceInfoList(ports);
     }
 
-    Ok(infos)
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{port_name_from_friendly_name, port_type};
+    use crate::PortType;
+
+    #[test]
+    fn test_port_type() {
+        assert_eq!(port_type("ACPI", "Serial"), PortType::Physical);
+        assert_eq!(port_type("FTDIBUS", "FTSER2K"), PortType::Physical);
+        assert_eq!(port_type("USB", "usbser"), PortType::Physical);
+        assert_eq!(port_type("BTHENUM", "BTHMODEM"), PortType::Virtual);
+        assert_eq!(port_type("COM0COM", "com0com"), PortType::Virtual);
+        assert_eq!(port_type("ROOT", ""), PortType::Virtual);
+        assert_eq!(port_type("", ""), PortType::Unknown);
+    }
+
+    #[test]
+    fn test_port_name_from_friendly_name() {
+        let parse = |name| port_name_from_friendly_name(name);
+        assert_eq!(parse("USB Serial Device (COM7)").as_deref(), Some("COM7"));
+        assert_eq!(parse("Port (A) (COM12) ").as_deref(), Some("COM12"));
+        assert_eq!(parse("Communications Port"), None);
+        assert_eq!(parse("Modem (LPT1)"), None);
+        assert_eq!(parse("Modem (COM)"), None);
+        assert_eq!(parse("Modem (COM1a)"), None);
+        assert_eq!(parse("(COM3) Modem"), None);
+        assert_eq!(parse(""), None);
+    }
 }
@@ -0,0 +1,51 @@
+use std::io;
+use windows_sys::Win32::Foundation::{
+    ERROR_ACCESS_DENIED, ERROR_BAD_COMMAND, ERROR_DEVICE_NOT_CONNECTED, ERROR_DEVICE_REMOVED,
+    ERROR_GEN_FAILURE, ERROR_INVALID_HANDLE,
+};
+
+use crate::Error;
+
+/// Map an error from a call on an open port to [`Error::Disconnected`] if it
+/// means the device is gone
+///
+/// Drivers disagree on how they report an unplugged device; USB-serial
+/// drivers commonly fail every call with access denied or a bad command.
+/// When opening a port these codes mean something else, e.g. access denied
+/// for a port held by another process, so `CreateFileW` errors are not
+/// mapped.
+pub(crate) fn from_io(err: io::Error) -> Error {
+    match err.raw_os_error().map(|code| code as u32) {
+        Some(
+            ERROR_ACCESS_DENIED
+            | ERROR_BAD_COMMAND
+            | ERROR_DEVICE_NOT_CONNECTED
+            | ERROR_DEVICE_REMOVED
+            | ERROR_GEN_FAILURE
+            | ERROR_INVALID_HANDLE,
+        ) => Error::Disconnected(Some(err)),
+        _ => Error::Io(err),
+    }
+}
+
+/// The last error of the calling thread, from a call on an open port, see
+/// [`from_io`]
+pub(crate) fn last_os_error() -> Error {
+    from_io(io::Error::last_os_error())
+}
+
+#[cfg(test)]
+mod tests {
+    use windows_sys::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+
+    use super::*;
+
+    #[test]
+    fn test_from_io() {
+        let gone = io::Error::from_raw_os_error(ERROR_ACCESS_DENIED as i32);
+        assert!(matches!(from_io(gone), Error::Disconnected(Some(_))));
+
+        let other = io::Error::from_raw_os_error(ERROR_FILE_NOT_FOUND as i32);
+        assert!(matches!(from_io(other), Error::Io(_)));
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    future::poll_fn,
+    io,
+    os::windows::io::{AsRawHandle, OwnedHandle},
+    task::{Context, Poll},
+};
+
+use bitflags::bitflags;
+use windows_sys::Win32::{
+    Devices::Communication::{
+        EV_BREAK, EV_CTS, EV_DSR, EV_ERR, EV_RING, EV_RLSD, EV_RXCHAR, EV_RXFLAG, EV_TXEMPTY,
+        GetCommModemStatus, MS_CTS_ON, MS_DSR_ON, MS_RING_ON, MS_RLSD_ON, SetCommMask,
+        WaitCommEvent,
+    },
+    Foundation::{ERROR_IO_PENDING, HANDLE},
+};
+
+use crate::{
+    Result,
+    windows::{error, overlapped::Overlapped},
+};
+
+/// The levels of the modem status lines at one point in time
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModemStatus {
+    /// Clear To Send
+    pub cts: bool,
+    /// Data Set Ready
+    pub dsr: bool,
+    /// Ring Indicator
+    pub ri: bool,
+    /// Carrier Detect (RLSD)
+    pub cd: bool,
+}
+
+impl ModemStatus {
+    pub(crate) fn from_raw(status: u32) -> ModemStatus {
+        ModemStatus {
+            cts: status & MS_CTS_ON != 0,
+            dsr: status & MS_DSR_ON != 0,
+            ri: status & MS_RING_ON != 0,
+            cd: status & MS_RLSD_ON != 0,
+        }
+    }
+}
+
+bitflags! {
+    /// A set of the events a port signals through `WaitCommEvent`, see
+    /// `COMPort::wait_events`
+    ///
+    /// Events combine with `|`.
+    #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct CommEventMask: u32 {
+        /// A byte was received
+        const RX_CHAR = EV_RXCHAR;
+        /// The event character of the DCB was received
+        const RX_FLAG = EV_RXFLAG;
+        /// The last byte of the output queue was sent
+        const TX_EMPTY = EV_TXEMPTY;
+        /// CTS changed
+        const CTS = EV_CTS;
+        /// DSR changed
+        const DSR = EV_DSR;
+        /// Carrier detect (RLSD) changed
+        const RLSD = EV_RLSD;
+        /// A break was received
+        const BREAK = EV_BREAK;
+        /// A framing, overrun or parity error occurred
+        const ERR = EV_ERR;
+        /// The ring indicator went on
+        const RING = EV_RING;
+    }
+}
+
+pub(crate) fn modem_status(handle: HANDLE) -> Result<u32> {
+    let mut status: u32 = 0;
+
+    match unsafe { GetCommModemStatus(handle, &mut status) } {
+        0 => Err(error::last_os_error()),
+        _ => Ok(status),
+    }
+}
+
+/// Selects the events `WaitCommEvent` waits for, for every handle to the port
+pub(crate) fn set_comm_mask(handle: HANDLE, mask: u32) -> Result<()> {
+    match unsafe { SetCommMask(handle, mask) } {
+        0 => Err(error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Waits for comm events on a handle of its own, so the wait can outlive the
+/// borrow of the port it was started from
+pub(crate) struct CommEvents {
+    handle: OwnedHandle,
+    overlapped: Overlapped,
+    /// Where the OS stores the events that fired, boxed so its address stays
+    /// stable while a wait is in flight
+    fired: Box<u32>,
+    pending: bool,
+}
+
+impl CommEvents {
+    pub(crate) fn new(handle: OwnedHandle) -> Result<CommEvents> {
+        Ok(CommEvents {
+            handle,
+            overlapped: Overlapped::new()?,
+            fired: Box::new(0),
+            pending: false,
+        })
+    }
+
+    pub(crate) fn handle(&self) -> HANDLE {
+        self.handle.as_raw_handle()
+    }
+
+    /// Waits for any of the events in the comm mask and returns those that
+    /// fired, or 0 if the mask was changed meanwhile
+    ///
+    /// Cancellation safe: a wait left in flight by a dropped future is picked
+    /// up again by the next call.
+    pub(crate) async fn wait(&mut self) -> Result<u32> {
+        poll_fn(|cx| self.poll_wait(cx)).await
+    }
+
+    /// Poll form of [`CommEvents::wait`]
+    pub(crate) fn poll_wait(&mut self, cx: &mut Context<'_>) -> Poll<Result<u32>> {
+        if !self.pending {
+            let fired: *mut u32 = &mut *self.fired;
+            if unsafe { WaitCommEvent(self.handle(), fired, self.overlapped.start()) } == 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() != Some(ERROR_IO_PENDING as i32) {
+                    return Poll::Ready(Err(error::from_io(err)));
+                }
+            }
+            self.pending = true;
+        }
+
+        let handle = self.handle.as_raw_handle();
+        let result = std::task::ready!(self.overlapped.poll(handle, cx));
+        self.pending = false;
+        Poll::Ready(result.map(|_| *self.fired).map_err(error::from_io))
+    }
+}
+
+impl Drop for CommEvents {
+    fn drop(&mut self) {
+        // The OS must be done with the mask before it is freed
+        if self.pending {
+            let _ = self.overlapped.cancel(self.handle());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows_sys::Win32::Devices::Communication::{EV_CTS, EV_RXCHAR, MS_CTS_ON, MS_RLSD_ON};
+
+    use super::{CommEventMask, ModemStatus};
+
+    #[test]
+    fn test_comm_event_mask() {
+        let mask = CommEventMask::RX_CHAR | CommEventMask::CTS;
+        assert_eq!(mask.bits(), EV_RXCHAR | EV_CTS);
+        assert!(mask.contains(CommEventMask::CTS));
+        assert!(!mask.contains(CommEventMask::CTS | CommEventMask::BREAK));
+        assert!(CommEventMask::default().is_empty());
+    }
+
+    #[test]
+    fn test_modem_status_from_raw() {
+        assert_eq!(ModemStatus::from_raw(0), ModemStatus::default());
+        assert_eq!(
+            ModemStatus::from_raw(MS_CTS_ON | MS_RLSD_ON),
+            ModemStatus {
+                cts: true,
+                cd: true,
+                ..ModemStatus::default()
+            }
+        );
+    }
+}
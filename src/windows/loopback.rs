@@ -0,0 +1,492 @@
+//! Finds installed com0com virtual port pairs to run the I/O tests against
+//!
+//! com0com names the two ends of pair `n` `CNCAn` and `CNCBn` in the friendly
+//! name, e.g. `com0com - serial port emulator CNCA0 (COM10)`. When no pair is
+//! installed the loopback tests pass without doing anything, so they can
+//! stay enabled everywhere.
+
+use crate::{PortInfo, available_ports};
+
+/// The end (`'A'` or `'B'`) and number of a com0com port, from its friendly
+/// name
+fn pair_end(name: &str) -> Option<(char, u32)> {
+    let (_, rest) = name.split_once("CNC")?;
+    let mut chars = rest.chars();
+    let end = chars.next().filter(|c| matches!(c, 'A' | 'B'))?;
+    let digits: String = chars.take_while(|c| c.is_ascii_digit()).collect();
+    Some((end, digits.parse().ok()?))
+}
+
+/// The paths of both ends of every complete com0com pair among `ports`
+fn pairs(ports: &[PortInfo]) -> Vec<(String, String)> {
+    let ends: Vec<_> = ports
+        .iter()
+        .filter(|port| !port.path.is_empty())
+        .filter_map(|port| Some((pair_end(&port.name)?, &port.path)))
+        .collect();
+
+    let mut pairs: Vec<_> = ends
+        .iter()
+        .filter(|((end, _), _)| *end == 'A')
+        .filter_map(|((_, n), a)| {
+            let (_, b) = ends.iter().find(|((end, m), _)| *end == 'B' && m == n)?;
+            Some((*n, (a.to_string(), b.to_string())))
+        })
+        .collect();
+    pairs.sort_by_key(|(n, _)| *n);
+    pairs.into_iter().map(|(_, pair)| pair).collect()
+}
+
+/// The first installed com0com pair, if any
+pub(crate) fn com0com_pair() -> Option<(String, String)> {
+    pairs(&available_ports().ok()?).into_iter().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::IoSlice, time::Duration};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{com0com_pair, pair_end, pairs};
+    use crate::{
+        COMPort, Clear, CommEventMask, OverrunPolicy, Parity, PortConfig, PortInfo, PortType,
+        PurgeFlags, RtsLevel, SerialPort, SerialPortBuilder, StopBits, new,
+    };
+
+    fn port(path: &str, name: &str) -> PortInfo {
+        PortInfo {
+            path: path.to_owned(),
+            name: name.to_owned(),
+            port_type: PortType::Virtual,
+            available: None,
+        }
+    }
+
+    /// Builders for both ends of the first installed com0com pair at
+    /// `baud_rate`, or `None` once the test is noted as skipped
+    fn pair_builders(baud_rate: u32) -> Option<(SerialPortBuilder, SerialPortBuilder)> {
+        let Some((a, b)) = com0com_pair() else {
+            eprintln!("no com0com pair installed, skipping");
+            return None;
+        };
+        Some((new(&a, baud_rate), new(&b, baud_rate)))
+    }
+
+    /// Both ends of the first installed com0com pair, opened at `baud_rate`
+    fn open_pair(baud_rate: u32) -> Option<(COMPort, COMPort)> {
+        let (a, b) = pair_builders(baud_rate)?;
+        Some((a.open().unwrap(), b.open().unwrap()))
+    }
+
+    #[test]
+    fn test_pair_end() {
+        assert_eq!(
+            pair_end("com0com - serial port emulator CNCA0 (COM10)"),
+            Some(('A', 0))
+        );
+        assert_eq!(
+            pair_end("com0com - serial port emulator CNCB12"),
+            Some(('B', 12))
+        );
+        assert_eq!(pair_end("Communications Port (COM1)"), None);
+        assert_eq!(pair_end("CNCC0"), None);
+    }
+
+    #[test]
+    fn test_pairs() {
+        let ports = [
+            port("COM11", "com0com - serial port emulator CNCB0 (COM11)"),
+            port("COM1", "Communications Port (COM1)"),
+            port("COM12", "com0com - serial port emulator CNCA1 (COM12)"),
+            port("COM10", "com0com - serial port emulator CNCA0 (COM10)"),
+        ];
+        assert_eq!(pairs(&ports), [("COM10".to_owned(), "COM11".to_owned())]);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_round_trip() {
+        let Some((mut a, mut b)) = open_pair(115200) else {
+            return;
+        };
+
+        a.write_all(b"ping").await.unwrap();
+        a.flush().await.unwrap();
+        let mut buf = [0; 4];
+        b.read_exact_timeout(&mut buf, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(&buf, b"ping");
+
+        b.write_all(b"pong").await.unwrap();
+        b.flush().await.unwrap();
+        a.read_exact_timeout(&mut buf, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+
+    #[tokio::test]
+    async fn test_loopback_write_vectored() {
+        let Some((mut a, mut b)) = open_pair(115200) else {
+            return;
+        };
+
+        let bufs = [IoSlice::new(b"head"), IoSlice::new(b"payload")];
+        assert_eq!(a.write_vectored(&bufs).await.unwrap(), 11);
+        a.flush().await.unwrap();
+
+        let mut buf = [0; 11];
+        b.read_exact_timeout(&mut buf, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(&buf, b"headpayload");
+    }
+
+    #[tokio::test]
+    async fn test_loopback_read_frame() {
+        let Some((mut a, mut b)) = open_pair(115200) else {
+            return;
+        };
+
+        a.write_all(b"framed").await.unwrap();
+        a.flush().await.unwrap();
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(b.read_frame(4, timeout).await.unwrap(), b"fram");
+        assert_eq!(b.read_frame(2, timeout).await.unwrap(), b"ed");
+        assert!(matches!(
+            b.read_frame(1, Duration::from_millis(10)).await,
+            Err(crate::Error::TimedOut)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_loopback_sync_write() {
+        let Some((mut a, mut b)) = open_pair(115200) else {
+            return;
+        };
+        a.set_sync_threshold(4);
+
+        // Below the threshold a write the driver completes at once is
+        // collected in place, above it the usual overlapped path takes over
+        a.write_all(b"ack").await.unwrap();
+        a.write_all(b"payload").await.unwrap();
+        a.flush().await.unwrap();
+
+        let mut buf = [0; 10];
+        b.read_exact_timeout(&mut buf, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(&buf, b"ackpayload");
+    }
+
+    #[tokio::test]
+    async fn test_loopback_wait_write_space() {
+        let Some((mut a, _b)) = open_pair(115200) else {
+            return;
+        };
+
+        a.write_all(b"payload").await.unwrap();
+        a.wait_write_space_timeout(1, Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        let (_, capacity) = a.queue_capacity().unwrap();
+        if capacity > 0 {
+            assert!(matches!(
+                a.wait_write_space(capacity + 1).await,
+                Err(crate::Error::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_loopback_parity() {
+        let Some((a, _)) = pair_builders(115200) else {
+            return;
+        };
+
+        for parity in [
+            Parity::None,
+            Parity::Odd,
+            Parity::Even,
+            Parity::Mark,
+            Parity::Space,
+        ] {
+            let port = a.clone().parity(parity).open().unwrap();
+            assert_eq!(port.parity().unwrap(), parity);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_loopback_set_config_atomic() {
+        let Some((mut a, _b)) = open_pair(115200) else {
+            return;
+        };
+        let saved = a.effective_config().unwrap();
+
+        // 1.5 stop bits only go with 5 data bits, so the driver refuses the
+        // whole set once the baud rate is part of it
+        let rejected = PortConfig {
+            baud_rate: 9600,
+            stop_bits: StopBits::OnePointFive,
+            ..saved
+        };
+        if a.set_config_atomic(&rejected).is_ok() {
+            println!("driver takes 1.5 stop bits with 8 data bits, skipping");
+            return;
+        }
+        assert_eq!(a.effective_config().unwrap(), saved);
+
+        let accepted = PortConfig {
+            baud_rate: 9600,
+            ..saved
+        };
+        a.set_config_atomic(&accepted).unwrap();
+        assert_eq!(a.effective_config().unwrap(), accepted);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_overrun_policy() {
+        let Some((a, b)) = pair_builders(115200) else {
+            return;
+        };
+        let mut a = a.open().unwrap();
+        let mut b = b
+            .queue_sizes(64, 64)
+            .overrun_policy(OverrunPolicy::Error)
+            .open()
+            .unwrap();
+
+        // Far more than the receiver's queue holds, with nobody reading; the
+        // write may stall if the emulator holds it back instead
+        let flood = tokio::time::timeout(Duration::from_millis(500), a.write_all(&[0x55; 4096]));
+        let _ = flood.await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        b.bytes_to_read().unwrap();
+        if b.overrun_count() == 0 {
+            println!("emulator reports no overrun, skipping");
+            return;
+        }
+
+        // The overrun fails one read, the next carries on
+        let mut buf = [0; 64];
+        let err = b.read(&mut buf).await.unwrap_err();
+        let err = err.get_ref().and_then(|e| e.downcast_ref::<crate::Error>());
+        assert!(matches!(err, Some(crate::Error::Overrun)));
+        assert!(b.read(&mut buf).await.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_clear_with() {
+        let Some((mut a, b)) = open_pair(115200) else {
+            return;
+        };
+
+        a.write_all(b"stale").await.unwrap();
+        a.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(b.bytes_to_read().unwrap(), 5);
+
+        b.clear_with(PurgeFlags::RX_CLEAR).unwrap();
+        assert_eq!(b.bytes_to_read().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_clear_held_input() {
+        let Some((mut a, mut b)) = open_pair(115200) else {
+            return;
+        };
+
+        // Peeked bytes are held by the port, and cleared with the input
+        a.write_all(b"stale").await.unwrap();
+        a.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut buf = [0; 5];
+        assert_eq!(b.peek(&mut buf).await.unwrap(), 5);
+        assert_eq!(b.bytes_to_read().unwrap(), 5);
+        b.clear(Clear::Input).unwrap();
+        assert_eq!(b.bytes_to_read().unwrap(), 0);
+
+        // So are the bytes of a read left in flight
+        let read = tokio::time::timeout(Duration::from_millis(10), b.read(&mut buf));
+        assert!(read.await.is_err());
+        a.write_all(b"stale").await.unwrap();
+        a.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        b.clear(Clear::Input).unwrap();
+
+        a.write_all(b"fresh").await.unwrap();
+        a.flush().await.unwrap();
+        b.read_exact_timeout(&mut buf, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(&buf, b"fresh");
+    }
+
+    #[tokio::test]
+    async fn test_loopback_rs485_manual() {
+        let Some((a, b)) = pair_builders(115200) else {
+            return;
+        };
+        let mut a = a.rs485_manual(RtsLevel::High).open().unwrap();
+        let mut b = b.open().unwrap();
+
+        // com0com wires each end's RTS to the other's CTS
+        assert!(!b.cts().unwrap());
+        a.write_all(b"request").await.unwrap();
+        assert!(b.cts().unwrap());
+        a.flush().await.unwrap();
+        assert!(!b.cts().unwrap());
+
+        let mut buf = [0; 7];
+        b.read_exact_timeout(&mut buf, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(&buf, b"request");
+
+        // Nothing written since, so a flush returns at once
+        a.flush().await.unwrap();
+        assert!(!b.cts().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_loopback_wait_events() {
+        let Some((mut a, b)) = open_pair(115200) else {
+            return;
+        };
+
+        let mask = CommEventMask::RX_CHAR | CommEventMask::BREAK;
+        let (fired, _) = tokio::join!(
+            tokio::time::timeout(Duration::from_secs(1), b.wait_events(mask)),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                a.write_all(b"x").await.unwrap();
+                a.flush().await.unwrap();
+            }
+        );
+        assert_eq!(fired.unwrap().unwrap(), CommEventMask::RX_CHAR);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_drain_complete() {
+        let Some((mut a, _b)) = open_pair(115200) else {
+            return;
+        };
+
+        a.write_all(&[0x5a; 256]).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), a.drain_complete())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(a.bytes_to_write().unwrap(), 0);
+
+        // Nothing left to send
+        tokio::time::timeout(Duration::from_secs(1), a.drain_complete())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_loopback_flow_limits() {
+        let Some((a, _)) = pair_builders(115200) else {
+            return;
+        };
+
+        let mut port = a.flow_limits(512, 128).open().unwrap();
+        assert_eq!(port.flow_limits().unwrap(), (512, 128));
+
+        port.set_flow_limits(256, 64).unwrap();
+        assert_eq!(port.flow_limits().unwrap(), (256, 64));
+    }
+
+    #[tokio::test]
+    async fn test_loopback_to_builder() {
+        let Some((a, _)) = pair_builders(19200) else {
+            return;
+        };
+
+        let builder = a
+            .parity(Parity::Even)
+            .flow_limits(512, 128)
+            .error_replacement(Some(b'?'));
+        let port = builder.clone().open().unwrap();
+        let derived = port.to_builder().unwrap();
+        assert_eq!(derived, builder);
+
+        // The derived builder opens a port configured the same way
+        drop(port);
+        let port = derived.open().unwrap();
+        assert_eq!(port.parity().unwrap(), Parity::Even);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_pending_io() {
+        let Some((mut a, mut b)) = open_pair(115200) else {
+            return;
+        };
+        assert!(!a.has_pending_read());
+        assert!(!a.has_pending_write());
+
+        // A read given up on stays in flight
+        let mut buf = [0; 4];
+        let read = tokio::time::timeout(Duration::from_millis(10), a.read(&mut buf));
+        assert!(read.await.is_err());
+        assert!(a.has_pending_read());
+        assert_eq!(a.cancel_read(), 0);
+        assert!(!a.has_pending_read());
+
+        b.write_all(b"data").await.unwrap();
+        assert!(b.has_pending_write());
+        b.flush().await.unwrap();
+        assert!(!b.has_pending_write());
+    }
+
+    #[tokio::test]
+    async fn test_loopback_flush_on_drop() {
+        let Some((a, b)) = pair_builders(115200) else {
+            return;
+        };
+        let mut a = a.flush_on_drop(true).open().unwrap();
+        let mut b = b.open().unwrap();
+
+        // Dropped without a flush, the write still goes out in full
+        let payload = vec![0x5a; 4096];
+        a.write_all(&payload).await.unwrap();
+        drop(a);
+
+        let mut buf = vec![0; payload.len()];
+        b.read_exact_timeout(&mut buf, Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(buf, payload);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_monitor_modem() {
+        let Some((mut a, b)) = open_pair(115200) else {
+            return;
+        };
+
+        // com0com wires each end's DTR to the other's DSR
+        let mut statuses = b.monitor_modem().unwrap();
+        let initial = statuses.recv().await.unwrap();
+        a.set_dtr(!initial.dsr).unwrap();
+
+        let changed = tokio::time::timeout(Duration::from_secs(1), statuses.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(changed.dsr, !initial.dsr);
+
+        // Closing the port ends the monitor
+        drop(b);
+        let end = tokio::time::timeout(Duration::from_secs(1), async {
+            while statuses.recv().await.is_some() {}
+        });
+        end.await.unwrap();
+    }
+}
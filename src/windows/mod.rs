@@ -1,6 +1,18 @@
 pub use self::com::*;
 pub use self::enumerate::*;
+pub use self::events::{CommEventMask, ModemStatus};
+pub use self::properties::*;
+pub use self::purge::PurgeFlags;
+pub use self::timeouts::CommTimeouts;
 
 mod com;
 mod dcb;
 mod enumerate;
+mod error;
+mod events;
+#[cfg(test)]
+mod loopback;
+mod overlapped;
+mod properties;
+mod purge;
+mod timeouts;
@@ -0,0 +1,355 @@
+use std::{
+    cell::UnsafeCell,
+    ffi::c_void,
+    io::{self, Error},
+    os::windows::io::{FromRawHandle, IntoRawHandle, OwnedHandle},
+    ptr::{null, null_mut},
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+};
+
+use windows_sys::Win32::{
+    Foundation::{
+        CloseHandle, ERROR_IO_INCOMPLETE, ERROR_IO_PENDING, HANDLE, INVALID_HANDLE_VALUE,
+        STATUS_PENDING,
+    },
+    System::{
+        IO::{CancelIoEx, GetOverlappedResult, OVERLAPPED},
+        Threading::{
+            CreateEventW, INFINITE, RegisterWaitForSingleObject, UnregisterWaitEx,
+            WT_EXECUTEONLYONCE,
+        },
+    },
+};
+
+/// An `OVERLAPPED` paired with its own manual-reset event, able to wake the
+/// task polling it once the operation completes
+///
+/// The `OVERLAPPED` and the waker slot are boxed so their addresses stay stable
+/// while the kernel and the thread pool hold on to them.
+pub(crate) struct Overlapped {
+    raw: Box<UnsafeCell<OVERLAPPED>>,
+    waker: Box<Mutex<Option<Waker>>>,
+    wait: HANDLE,
+}
+
+unsafe impl Send for Overlapped {}
+
+impl Overlapped {
+    /// With an unnamed manual-reset event of its own, see [`Overlapped::with_event`]
+    pub(crate) fn new() -> io::Result<Overlapped> {
+        // Manual reset, created non-signaled
+        let event = unsafe { CreateEventW(null(), 1, 0, null()) };
+        if event.is_null() {
+            return Err(Error::last_os_error());
+        }
+        Ok(Overlapped::with_event(unsafe {
+            OwnedHandle::from_raw_handle(event)
+        }))
+    }
+
+    /// With `event` to signal completion, e.g. one created with attributes or
+    /// a name of its own
+    ///
+    /// The event must be manual-reset and used by no other operation. The OS
+    /// resets it as it starts an operation and signals it once the operation
+    /// completes, after which it stays signaled for both the thread pool wait
+    /// that wakes the task and the `GetOverlappedResult` that follows. An
+    /// auto-reset event would be reset again by the first of the two, leaving
+    /// a blocking `GetOverlappedResult` waiting for good.
+    pub(crate) fn with_event(event: OwnedHandle) -> Overlapped {
+        let raw = OVERLAPPED {
+            hEvent: event.into_raw_handle(),
+            ..Default::default()
+        };
+
+        Overlapped {
+            raw: Box::new(UnsafeCell::new(raw)),
+            waker: Box::new(Mutex::new(None)),
+            wait: null_mut(),
+        }
+    }
+
+    /// Prepare for a new operation, returning the pointer to pass to the OS
+    pub(crate) fn start(&mut self) -> *mut OVERLAPPED {
+        let raw = self.raw.get_mut();
+        *raw = OVERLAPPED {
+            hEvent: raw.hEvent,
+            ..Default::default()
+        };
+        raw
+    }
+
+    /// Poll the operation, scheduling a wake-up of `cx` while it is in flight
+    pub(crate) fn poll(&mut self, handle: HANDLE, cx: &mut Context<'_>) -> Poll<io::Result<u32>> {
+        // Store the waker before checking, so a completion racing with the
+        // check is never missed
+        if let Ok(mut waker) = self.waker.lock() {
+            match waker.as_ref() {
+                Some(waker) if waker.will_wake(cx.waker()) => (),
+                _ => *waker = Some(cx.waker().clone()),
+            }
+        }
+
+        match completion(self.result(handle, false)) {
+            Poll::Pending => {
+                self.register()?;
+                Poll::Pending
+            }
+            ready => {
+                self.unregister();
+                ready
+            }
+        }
+    }
+
+    /// Check whether the operation has completed, without scheduling a
+    /// wake-up
+    pub(crate) fn check(&mut self, handle: HANDLE) -> Poll<io::Result<u32>> {
+        completion(self.result(handle, false))
+    }
+
+    /// Whether the OS has completed the operation, read from the
+    /// `OVERLAPPED` the way `HasOverlappedIoCompleted` does
+    pub(crate) fn is_complete(&self) -> bool {
+        let status = unsafe { std::ptr::addr_of!((*self.raw.get()).Internal).read_volatile() };
+        status != STATUS_PENDING as usize
+    }
+
+    /// Block until the operation completes
+    pub(crate) fn wait(&mut self, handle: HANDLE) -> io::Result<u32> {
+        let result = self.result(handle, true);
+        self.unregister();
+        result
+    }
+
+    /// Cancel the operation and block until the OS is done with it, returning
+    /// its result
+    ///
+    /// One cut short fails with `ERROR_OPERATION_ABORTED`, see
+    /// [`Overlapped::transferred`] for how far it got; one that completed
+    /// before it could be cancelled returns as usual.
+    pub(crate) fn cancel(&mut self, handle: HANDLE) -> io::Result<u32> {
+        unsafe { CancelIoEx(handle, self.raw.get()) };
+        self.wait(handle)
+    }
+
+    /// How many bytes the completed operation transferred, also if it failed
+    /// or was cancelled part way
+    pub(crate) fn transferred(&self) -> u32 {
+        unsafe { (*self.raw.get()).InternalHigh as u32 }
+    }
+
+    fn result(&self, handle: HANDLE, wait: bool) -> io::Result<u32> {
+        let mut transferred = 0;
+        match unsafe { GetOverlappedResult(handle, self.raw.get(), &mut transferred, wait as _) } {
+            0 => Err(Error::last_os_error()),
+            _ => Ok(transferred),
+        }
+    }
+
+    /// Have the thread pool wake the task once the event is signaled
+    fn register(&mut self) -> io::Result<()> {
+        if !self.wait.is_null() {
+            return Ok(());
+        }
+
+        let registered = unsafe {
+            RegisterWaitForSingleObject(
+                &mut self.wait,
+                (*self.raw.get()).hEvent,
+                Some(wake),
+                &*self.waker as *const _ as *const c_void,
+                INFINITE,
+                WT_EXECUTEONLYONCE,
+            )
+        };
+
+        match registered {
+            0 => {
+                self.wait = null_mut();
+                Err(Error::last_os_error())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn unregister(&mut self) {
+        if !self.wait.is_null() {
+            // Waits for a running callback to return, so the waker slot is no
+            // longer referenced afterwards
+            unsafe { UnregisterWaitEx(self.wait, INVALID_HANDLE_VALUE) };
+            self.wait = null_mut();
+        }
+    }
+}
+
+impl Drop for Overlapped {
+    fn drop(&mut self) {
+        self.unregister();
+        unsafe { CloseHandle(self.raw.get_mut().hEvent) };
+    }
+}
+
+/// Tell an operation still in flight apart from one that has completed, given
+/// what a non-blocking `GetOverlappedResult` returned for it
+///
+/// Only the codes meaning "not done yet" are pending. Treating an error as
+/// pending would wait for a wake-up that never comes, and treating a pending
+/// operation as failed would lose its completion.
+fn completion(result: io::Result<u32>) -> Poll<io::Result<u32>> {
+    match result {
+        Err(e)
+            if e.raw_os_error() == Some(ERROR_IO_INCOMPLETE as i32)
+                || e.raw_os_error() == Some(ERROR_IO_PENDING as i32) =>
+        {
+            Poll::Pending
+        }
+        result => Poll::Ready(result),
+    }
+}
+
+unsafe extern "system" fn wake(context: *mut c_void, _timed_out: bool) {
+    let waker = unsafe { &*(context as *const Mutex<Option<Waker>>) };
+    if let Ok(mut waker) = waker.lock()
+        && let Some(waker) = waker.take()
+    {
+        waker.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{File, OpenOptions},
+        io::{self, Error, Write},
+        os::windows::ffi::OsStrExt,
+        ptr::null,
+        task::{Context, Poll, Waker},
+    };
+
+    use windows_sys::Win32::{
+        Foundation::{
+            CloseHandle, ERROR_ACCESS_DENIED, ERROR_IO_INCOMPLETE, ERROR_IO_PENDING, HANDLE,
+            INVALID_HANDLE_VALUE, WAIT_OBJECT_0, WAIT_TIMEOUT,
+        },
+        Storage::FileSystem::{FILE_FLAG_OVERLAPPED, PIPE_ACCESS_INBOUND, ReadFile},
+        System::{
+            Pipes::CreateNamedPipeW,
+            Threading::{SetEvent, WaitForSingleObject},
+        },
+    };
+
+    use super::{Overlapped, completion};
+
+    #[test]
+    fn test_event_manual_reset() {
+        let overlapped = Overlapped::new().unwrap();
+        let event = unsafe { (*overlapped.raw.get()).hEvent };
+        assert_eq!(unsafe { WaitForSingleObject(event, 0) }, WAIT_TIMEOUT);
+
+        // A completion stays visible to every wait, not just the first
+        assert_ne!(unsafe { SetEvent(event) }, 0);
+        assert_eq!(unsafe { WaitForSingleObject(event, 0) }, WAIT_OBJECT_0);
+        assert_eq!(unsafe { WaitForSingleObject(event, 0) }, WAIT_OBJECT_0);
+    }
+
+    #[test]
+    fn test_completion() {
+        let pending = |code| completion(Err(Error::from_raw_os_error(code as i32)));
+        assert!(pending(ERROR_IO_INCOMPLETE).is_pending());
+        assert!(pending(ERROR_IO_PENDING).is_pending());
+
+        match pending(ERROR_ACCESS_DENIED) {
+            Poll::Ready(Err(e)) => assert_eq!(e.raw_os_error(), Some(ERROR_ACCESS_DENIED as i32)),
+            _ => panic!("an error must complete the operation"),
+        }
+        assert!(matches!(completion(Ok(0)), Poll::Ready(Ok(0))));
+        assert!(matches!(completion(Ok(5)), Poll::Ready(Ok(5))));
+    }
+
+    /// An overlapped named pipe server and a client to write into it
+    fn pipe(test: &str) -> io::Result<(HANDLE, File)> {
+        let path = format!(r"\\.\pipe\serialport-{test}-{}", std::process::id());
+        let name: Vec<u16> = std::ffi::OsStr::new(&path)
+            .encode_wide()
+            .chain(Some(0))
+            .collect();
+
+        let server = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_INBOUND | FILE_FLAG_OVERLAPPED,
+                0,
+                1,
+                64,
+                64,
+                0,
+                null(),
+            )
+        };
+        if server == INVALID_HANDLE_VALUE {
+            return Err(Error::last_os_error());
+        }
+        let client = OpenOptions::new().write(true).open(&path)?;
+        Ok((server, client))
+    }
+
+    /// Start reading `buf` from `server`, which must have nothing to read yet
+    fn start_read(server: HANDLE, buf: &mut [u8], overlapped: &mut Overlapped) {
+        let started = unsafe {
+            ReadFile(
+                server,
+                buf.as_mut_ptr(),
+                buf.len() as u32,
+                std::ptr::null_mut(),
+                overlapped.start(),
+            )
+        };
+        assert_eq!(started, 0);
+        assert_eq!(
+            Error::last_os_error().raw_os_error(),
+            Some(ERROR_IO_PENDING as i32)
+        );
+    }
+
+    #[test]
+    fn test_poll_slow_read() -> io::Result<()> {
+        let (server, mut client) = pipe("slow-read")?;
+        let mut overlapped = Overlapped::new()?;
+        let mut buf = [0u8; 16];
+        start_read(server, &mut buf, &mut overlapped);
+
+        // Nothing was written yet, so the read is pending exactly once
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(overlapped.poll(server, &mut cx).is_pending());
+
+        client.write_all(b"slow")?;
+        match overlapped.poll(server, &mut cx) {
+            Poll::Ready(Ok(n)) => assert_eq!(&buf[..n as usize], b"slow"),
+            other => panic!("expected a completed read, got {other:?}"),
+        }
+
+        drop(client);
+        drop(overlapped);
+        unsafe { CloseHandle(server) };
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel() -> io::Result<()> {
+        let (server, client) = pipe("cancel")?;
+        let mut overlapped = Overlapped::new()?;
+        let mut buf = [0u8; 16];
+        start_read(server, &mut buf, &mut overlapped);
+
+        // Returns at once rather than waiting for data that never comes
+        assert!(overlapped.cancel(server).is_err());
+        assert_eq!(overlapped.transferred(), 0);
+
+        drop(client);
+        drop(overlapped);
+        unsafe { CloseHandle(server) };
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use windows_sys::Win32::Devices::Communication::{COMMPROP, GetCommProperties};
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::WindowsProgramming::{
+    BAUD_56K, BAUD_075, BAUD_110, BAUD_128K, BAUD_134_5, BAUD_150, BAUD_300, BAUD_600, BAUD_1200,
+    BAUD_1800, BAUD_2400, BAUD_4800, BAUD_7200, BAUD_9600, BAUD_14400, BAUD_19200, BAUD_38400,
+    BAUD_57600, BAUD_115200, PCF_16BITMODE, PCF_DTRDSR, PCF_INTTIMEOUTS, PCF_PARITY_CHECK,
+    PCF_RLSD, PCF_RTSCTS, PCF_SETXCHAR, PCF_SPECIALCHARS, PCF_TOTALTIMEOUTS, PCF_XONXOFF, PST_FAX,
+    PST_LAT, PST_MODEM, PST_NETWORK_BRIDGE, PST_PARALLELPORT, PST_RS232, PST_RS422, PST_RS423,
+    PST_RS449, PST_SCANNER, PST_TCPIP_TELNET, PST_X25,
+};
+
+use crate::{Result, windows::error};
+
+/// Kind of device the driver of a port reports to serve
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProviderSubType {
+    Rs232,
+    Rs422,
+    Rs423,
+    Rs449,
+    Modem,
+    Fax,
+    Scanner,
+    ParallelPort,
+    NetworkBridge,
+    Lat,
+    TcpIpTelnet,
+    X25,
+    /// The driver did not report a subtype, or one not listed here
+    Unknown,
+}
+
+/// Features the driver of a port supports, as reported by `GetCommProperties`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// DTR/DSR flow control
+    pub const DTR_DSR: Capabilities = Capabilities(PCF_DTRDSR);
+    /// RTS/CTS flow control
+    pub const RTS_CTS: Capabilities = Capabilities(PCF_RTSCTS);
+    /// The carrier detect (RLSD) line
+    pub const RLSD: Capabilities = Capabilities(PCF_RLSD);
+    /// Parity checking
+    pub const PARITY_CHECK: Capabilities = Capabilities(PCF_PARITY_CHECK);
+    /// XON/XOFF flow control
+    pub const XON_XOFF: Capabilities = Capabilities(PCF_XONXOFF);
+    /// Settable XON/XOFF characters
+    pub const SETTABLE_XCHAR: Capabilities = Capabilities(PCF_SETXCHAR);
+    /// Total (elapsed) timeouts
+    pub const TOTAL_TIMEOUTS: Capabilities = Capabilities(PCF_TOTALTIMEOUTS);
+    /// Interval timeouts
+    pub const INTERVAL_TIMEOUTS: Capabilities = Capabilities(PCF_INTTIMEOUTS);
+    /// Special character support
+    pub const SPECIAL_CHARS: Capabilities = Capabilities(PCF_SPECIALCHARS);
+    /// Special 16-bit mode
+    pub const MODE_16BIT: Capabilities = Capabilities(PCF_16BITMODE);
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// Read-only metadata about a port and its driver
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortProperties {
+    pub provider_subtype: ProviderSubType,
+    pub capabilities: Capabilities,
+}
+
+impl From<&COMMPROP> for PortProperties {
+    fn from(prop: &COMMPROP) -> PortProperties {
+        PortProperties {
+            provider_subtype: provider_subtype(prop.dwProvSubType),
+            capabilities: Capabilities(prop.dwProvCapabilities),
+        }
+    }
+}
+
+fn provider_subtype(subtype: u32) -> ProviderSubType {
+    match subtype {
+        PST_RS232 => ProviderSubType::Rs232,
+        PST_RS422 => ProviderSubType::Rs422,
+        PST_RS423 => ProviderSubType::Rs423,
+        PST_RS449 => ProviderSubType::Rs449,
+        PST_MODEM => ProviderSubType::Modem,
+        PST_FAX => ProviderSubType::Fax,
+        PST_SCANNER => ProviderSubType::Scanner,
+        PST_PARALLELPORT => ProviderSubType::ParallelPort,
+        PST_NETWORK_BRIDGE => ProviderSubType::NetworkBridge,
+        PST_LAT => ProviderSubType::Lat,
+        PST_TCPIP_TELNET => ProviderSubType::TcpIpTelnet,
+        PST_X25 => ProviderSubType::X25,
+        _ => ProviderSubType::Unknown,
+    }
+}
+
+/// The highest rate of the `BAUD_*` flag in `dwMaxBaud`, or `None` if the
+/// driver has no fixed maximum (`BAUD_USER`) or reports an unknown one
+pub(crate) fn max_baud_rate(max_baud: u32) -> Option<u32> {
+    match max_baud {
+        BAUD_075 => Some(75),
+        BAUD_110 => Some(110),
+        BAUD_134_5 => Some(134),
+        BAUD_150 => Some(150),
+        BAUD_300 => Some(300),
+        BAUD_600 => Some(600),
+        BAUD_1200 => Some(1200),
+        BAUD_1800 => Some(1800),
+        BAUD_2400 => Some(2400),
+        BAUD_4800 => Some(4800),
+        BAUD_7200 => Some(7200),
+        BAUD_9600 => Some(9600),
+        BAUD_14400 => Some(14400),
+        BAUD_19200 => Some(19200),
+        BAUD_38400 => Some(38400),
+        BAUD_56K => Some(56000),
+        BAUD_57600 => Some(57600),
+        BAUD_115200 => Some(115200),
+        BAUD_128K => Some(128000),
+        _ => None,
+    }
+}
+
+pub(crate) fn get_properties(handle: HANDLE) -> Result<COMMPROP> {
+    let mut prop = COMMPROP::default();
+
+    if unsafe { GetCommProperties(handle, &mut prop) } != 0 {
+        Ok(prop)
+    } else {
+        Err(error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows_sys::Win32::System::WindowsProgramming::{BAUD_128K, BAUD_115200, BAUD_USER};
+
+    use super::max_baud_rate;
+
+    #[test]
+    fn test_max_baud_rate() {
+        assert_eq!(max_baud_rate(BAUD_115200), Some(115200));
+        assert_eq!(max_baud_rate(BAUD_128K), Some(128000));
+        assert_eq!(max_baud_rate(BAUD_USER), None);
+        assert_eq!(max_baud_rate(0), None);
+    }
+}
@@ -0,0 +1,57 @@
+use bitflags::bitflags;
+use windows_sys::Win32::Devices::Communication::{
+    PURGE_RXABORT, PURGE_RXCLEAR, PURGE_TXABORT, PURGE_TXCLEAR,
+};
+
+bitflags! {
+    /// What `PurgeComm` discards or aborts, see `COMPort::clear_with`
+    ///
+    /// Flags combine with `|`. Clearing a queue is safe while I/O is in flight:
+    /// a read or write the OS holds carries on, the read waiting for new bytes.
+    /// Aborting ends it early instead. An aborted read drops the bytes it did
+    /// receive along with the queue, so only abort reads to discard the input;
+    /// `COMPort::cancel_read` stops one and keeps its bytes instead. An aborted
+    /// write loses whatever part of it the driver had not sent, so only abort
+    /// writes when that data is meant to be dropped.
+    #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+    pub struct PurgeFlags: u32 {
+        /// Abort the read in flight
+        const RX_ABORT = PURGE_RXABORT;
+        /// Discard the bytes received but not read yet
+        const RX_CLEAR = PURGE_RXCLEAR;
+        /// Abort the write in flight
+        const TX_ABORT = PURGE_TXABORT;
+        /// Discard the bytes written but not sent yet
+        const TX_CLEAR = PURGE_TXCLEAR;
+    }
+}
+
+impl From<crate::Clear> for PurgeFlags {
+    fn from(clear: crate::Clear) -> PurgeFlags {
+        use crate::Clear;
+
+        match clear {
+            Clear::Input => PurgeFlags::RX_ABORT | PurgeFlags::RX_CLEAR,
+            Clear::Output => PurgeFlags::TX_ABORT | PurgeFlags::TX_CLEAR,
+            Clear::All => PurgeFlags::all(),
+            Clear::InputSoft => PurgeFlags::RX_CLEAR,
+            Clear::OutputSoft => PurgeFlags::TX_CLEAR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PurgeFlags;
+    use crate::Clear;
+
+    #[test]
+    fn test_from_clear() {
+        let all = PurgeFlags::from(Clear::All);
+        assert!(all.contains(PurgeFlags::from(Clear::Input)));
+        assert!(all.contains(PurgeFlags::from(Clear::Output)));
+        assert!(!PurgeFlags::from(Clear::InputSoft).contains(PurgeFlags::RX_ABORT));
+        assert_eq!(PurgeFlags::from(Clear::OutputSoft), PurgeFlags::TX_CLEAR);
+        assert_eq!(PurgeFlags::default().bits(), 0);
+    }
+}
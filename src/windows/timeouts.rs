@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use windows_sys::Win32::Devices::Communication::{COMMTIMEOUTS, GetCommTimeouts, SetCommTimeouts};
+use windows_sys::Win32::Foundation::HANDLE;
+
+use crate::{Result, windows::error};
+
+/// Read and write timeouts of a port, in milliseconds, as used by `SetCommTimeouts`
+///
+/// A read ends once it has all the bytes it asked for, or once one of its
+/// timeouts expires, returning the bytes received up to then:
+///
+/// * the interval timeout runs between two bytes, from the first one on;
+/// * the total timeout runs from the start of the read and is
+///   `read_total_multiplier` times the number of bytes asked for plus
+///   `read_total_constant`.
+///
+/// A field of 0 leaves that timeout out; with all of them 0 a read waits
+/// for as long as it takes. `read_interval` of `u32::MAX` with both total
+/// fields 0 makes reads return at once with whatever is buffered, which
+/// [`crate::COMPort`] does not support, see below. `read_interval` and
+/// `read_total_multiplier` of `u32::MAX` with a `read_total_constant`
+/// between 0 and `u32::MAX` make a read return as soon as any byte is
+/// buffered, waiting up to the constant for the first one. Writes only have
+/// the total timeout, counted in bytes written.
+///
+/// The read path of [`crate::COMPort`] is built around the timeouts it sets
+/// when opening, see [`crate::COMPort::get_timeouts`]: a read that a timeout
+/// ends without any bytes is issued again, so reads still wait for data and
+/// a timeout only bounds each attempt. Use `tokio::time::timeout` to give up
+/// on a read. Timeouts that return at once would have it issue reads over
+/// and over, so [`crate::COMPort::set_timeouts`] refuses them; use
+/// [`crate::SerialPort::bytes_to_read`] to look at what is buffered.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommTimeouts {
+    /// Longest gap between two received bytes, `ReadIntervalTimeout`
+    pub read_interval: u32,
+    /// Time per byte asked for added to the total read timeout,
+    /// `ReadTotalTimeoutMultiplier`
+    pub read_total_multiplier: u32,
+    /// Time added to the total read timeout once per read,
+    /// `ReadTotalTimeoutConstant`
+    pub read_total_constant: u32,
+    /// Time per byte written added to the total write timeout,
+    /// `WriteTotalTimeoutMultiplier`
+    pub write_total_multiplier: u32,
+    /// Time added to the total write timeout once per write,
+    /// `WriteTotalTimeoutConstant`
+    pub write_total_constant: u32,
+}
+
+impl CommTimeouts {
+    /// Reads end after `timeout` in total, however many bytes they asked for
+    ///
+    /// `timeout` is rounded up to whole milliseconds, at least one.
+    pub fn total(timeout: Duration) -> CommTimeouts {
+        CommTimeouts {
+            read_total_constant: millis(timeout),
+            ..Default::default()
+        }
+    }
+
+    /// Reads wait for the first byte, then end once the line is quiet for
+    /// `gap`
+    ///
+    /// `gap` is rounded up to whole milliseconds, at least one.
+    pub fn interval(gap: Duration) -> CommTimeouts {
+        CommTimeouts {
+            read_interval: millis(gap),
+            ..Default::default()
+        }
+    }
+
+    /// Whether reads return at once with whatever is buffered, possibly
+    /// nothing
+    pub(crate) fn is_non_blocking(&self) -> bool {
+        self.read_interval == u32::MAX
+            && self.read_total_multiplier == 0
+            && self.read_total_constant == 0
+    }
+}
+
+impl From<CommTimeouts> for COMMTIMEOUTS {
+    fn from(timeouts: CommTimeouts) -> COMMTIMEOUTS {
+        COMMTIMEOUTS {
+            ReadIntervalTimeout: timeouts.read_interval,
+            ReadTotalTimeoutMultiplier: timeouts.read_total_multiplier,
+            ReadTotalTimeoutConstant: timeouts.read_total_constant,
+            WriteTotalTimeoutMultiplier: timeouts.write_total_multiplier,
+            WriteTotalTimeoutConstant: timeouts.write_total_constant,
+        }
+    }
+}
+
+impl From<COMMTIMEOUTS> for CommTimeouts {
+    fn from(timeouts: COMMTIMEOUTS) -> CommTimeouts {
+        CommTimeouts {
+            read_interval: timeouts.ReadIntervalTimeout,
+            read_total_multiplier: timeouts.ReadTotalTimeoutMultiplier,
+            read_total_constant: timeouts.ReadTotalTimeoutConstant,
+            write_total_multiplier: timeouts.WriteTotalTimeoutMultiplier,
+            write_total_constant: timeouts.WriteTotalTimeoutConstant,
+        }
+    }
+}
+
+/// `duration` in whole milliseconds, rounded up, between 1 and the largest
+/// value that has no special meaning
+fn millis(duration: Duration) -> u32 {
+    duration
+        .as_micros()
+        .div_ceil(1000)
+        .clamp(1, u128::from(u32::MAX - 1)) as u32
+}
+
+/// Timeouts the read path is built around: a read returns as soon as any bytes
+/// are buffered, and otherwise waits for the first byte to arrive
+pub(crate) const DEFAULT: COMMTIMEOUTS = COMMTIMEOUTS {
+    ReadIntervalTimeout: u32::MAX,
+    ReadTotalTimeoutMultiplier: u32::MAX,
+    ReadTotalTimeoutConstant: u32::MAX - 1,
+    WriteTotalTimeoutMultiplier: 0,
+    WriteTotalTimeoutConstant: 0,
+};
+
+/// Timeouts for [`crate::SerialPortBuilder::read_coalesce`]: a read waits
+/// for the first byte, then gathers more until the line is quiet for `gap`
+pub(crate) fn coalescing(gap: Duration) -> COMMTIMEOUTS {
+    CommTimeouts::interval(gap).into()
+}
+
+pub(crate) fn get_timeouts(handle: HANDLE) -> Result<COMMTIMEOUTS> {
+    let mut timeouts = COMMTIMEOUTS::default();
+
+    if unsafe { GetCommTimeouts(handle, &mut timeouts) } != 0 {
+        Ok(timeouts)
+    } else {
+        Err(error::last_os_error())
+    }
+}
+
+pub(crate) fn set_timeouts(handle: HANDLE, timeouts: &COMMTIMEOUTS) -> Result<()> {
+    if unsafe { SetCommTimeouts(handle, timeouts) } != 0 {
+        Ok(())
+    } else {
+        Err(error::last_os_error())
+    }
+}
+
+/// Restores the saved timeouts when dropped, so an override is undone even if
+/// the future applying it is cancelled
+pub(crate) struct RestoreGuard {
+    handle: HANDLE,
+    saved: COMMTIMEOUTS,
+}
+
+unsafe impl Send for RestoreGuard {}
+
+impl RestoreGuard {
+    pub(crate) fn new(handle: HANDLE) -> Result<RestoreGuard> {
+        Ok(RestoreGuard {
+            handle,
+            saved: get_timeouts(handle)?,
+        })
+    }
+
+    pub(crate) fn restore(self) -> Result<()> {
+        let result = set_timeouts(self.handle, &self.saved);
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        let _ = set_timeouts(self.handle, &self.saved);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use windows_sys::Win32::Devices::Communication::COMMTIMEOUTS;
+
+    use super::{CommTimeouts, coalescing};
+
+    #[test]
+    fn test_raw_round_trip() {
+        let timeouts = CommTimeouts {
+            read_interval: 1,
+            read_total_multiplier: 2,
+            read_total_constant: 3,
+            write_total_multiplier: 4,
+            write_total_constant: 5,
+        };
+        assert_eq!(CommTimeouts::from(COMMTIMEOUTS::from(timeouts)), timeouts);
+    }
+
+    #[test]
+    fn test_constructors() {
+        let total = CommTimeouts::total(Duration::from_micros(1500));
+        assert_eq!(total.read_total_constant, 2);
+        assert_eq!(total.read_interval, 0);
+
+        let interval = CommTimeouts::interval(Duration::ZERO);
+        assert_eq!(interval.read_interval, 1);
+        assert_eq!(interval.read_total_constant, 0);
+    }
+
+    #[test]
+    fn test_is_non_blocking() {
+        let non_blocking = CommTimeouts {
+            read_interval: u32::MAX,
+            ..Default::default()
+        };
+        assert!(non_blocking.is_non_blocking());
+        assert!(!CommTimeouts::default().is_non_blocking());
+        assert!(!CommTimeouts::from(super::DEFAULT).is_non_blocking());
+        assert!(!CommTimeouts::interval(Duration::from_millis(5)).is_non_blocking());
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let gap = |d| coalescing(d).ReadIntervalTimeout;
+        assert_eq!(gap(Duration::from_millis(5)), 5);
+        assert_eq!(gap(Duration::from_micros(1500)), 2);
+        assert_eq!(gap(Duration::ZERO), 1);
+        assert_eq!(gap(Duration::from_secs(u64::MAX)), u32::MAX - 1);
+        assert_eq!(
+            coalescing(Duration::from_millis(5)).ReadTotalTimeoutConstant,
+            0
+        );
+    }
+}